@@ -1,7 +1,5 @@
 #![allow(clippy::bool_assert_comparison)]
 
-use std::collections::BTreeMap;
-use std::collections::BTreeSet;
 use std::collections::VecDeque;
 use std::io::Write;
 use std::path::Path;
@@ -10,18 +8,19 @@ use std::path::PathBuf;
 use cargo_edit_9::CargoResult;
 use cargo_edit_9::Context;
 use cargo_edit_9::{
-    colorize_stderr, find, manifest_from_pkgid, registry_url, update_registry_index, Dependency,
-    LocalManifest,
+    closest_match, colorize_stderr, is_offline, manifest_from_pkgid, registry_url,
+    update_registry_index, Dependency, FeatureCleanup, LocalManifest, ManifestTransaction,
 };
 use cargo_edit_9::{
     get_features_from_registry, get_manifest_from_path, get_manifest_from_url, workspace_members,
 };
-use cargo_edit_9::{get_latest_dependency, CrateSpec};
+use cargo_edit_9::{get_latest_dependency, CrateSpec, RustVersion};
 use cargo_metadata::Package;
 use clap::Args;
 use indexmap::IndexSet;
 use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
 use toml_edit::Item as TomlItem;
+use url::Url;
 
 /// Add dependencies to a Cargo.toml manifest file.
 #[derive(Debug, Args)]
@@ -64,9 +63,14 @@ pub struct AddArgs {
     #[clap(short = 'F', long)]
     pub features: Option<Vec<String>>,
 
+    /// Fail instead of warning when a requested feature is not exposed by the dependency
+    #[clap(long)]
+    pub strict_features: bool,
+
     /// Mark the dependency as optional
     ///
-    /// The package name will be exposed as feature of your crate.
+    /// The package name will be exposed as feature of your crate, unless `--in-feature` enables
+    /// it instead.
     #[clap(long, conflicts_with = "dev")]
     pub optional: bool,
 
@@ -76,6 +80,12 @@ pub struct AddArgs {
     #[clap(long, conflicts_with = "dev", overrides_with = "optional")]
     pub no_optional: bool,
 
+    /// Enable the optional dependency through `dep:<name>` in this feature, creating it if needed
+    ///
+    /// This keeps the package name out of the features of your crate.
+    #[clap(long, value_name = "FEATURE", requires = "optional")]
+    pub in_feature: Option<String>,
+
     /// Rename the dependency
     ///
     /// Example uses:{n}
@@ -88,6 +98,47 @@ pub struct AddArgs {
     #[clap(long, conflicts_with = "git")]
     pub registry: Option<String>,
 
+    /// Index URL of the package registry for this dependency
+    #[clap(
+        long,
+        value_name = "URL",
+        conflicts_with = "git",
+        conflicts_with = "registry"
+    )]
+    pub registry_index: Option<String>,
+
+    /// Mark the dependency as public
+    ///
+    /// The dependency can be referenced in your library's public API.
+    #[clap(long, conflicts_with = "dev", conflicts_with = "build")]
+    pub public: bool,
+
+    /// Mark the dependency as private
+    ///
+    /// While you can use the crate in your implementation, it cannot be referenced in your public API.
+    #[clap(
+        long,
+        conflicts_with = "dev",
+        conflicts_with = "build",
+        overrides_with = "public"
+    )]
+    pub no_public: bool,
+
+    /// Depend on artifacts of the dependency, like `bin`, `bin:<name>`, `cdylib` or `staticlib`
+    #[clap(long, value_name = "KIND")]
+    pub artifact: Option<Vec<String>>,
+
+    /// Depend on the library of an artifact dependency as well
+    #[clap(long, requires = "artifact")]
+    pub lib: bool,
+
+    /// Declare the dependency in `[workspace.dependencies]` and inherit it
+    ///
+    /// The version, source and default features are recorded in the workspace root while
+    /// features and `optional` stay with the package.
+    #[clap(long)]
+    pub workspace_dep: bool,
+
     /// Add as development dependency
     ///
     /// Dev-dependencies are not used when compiling a package for building, but are used for compiling tests, examples, and benchmarks.
@@ -124,6 +175,11 @@ pub struct AddArgs {
     #[clap(long)]
     pub offline: bool,
 
+    /// Pick the latest version even if it requires a newer Rust than the package's
+    /// `rust-version`
+    #[clap(long)]
+    pub ignore_rust_version: bool,
+
     /// Don't actually write the manifest
     #[clap(long)]
     pub dry_run: bool,
@@ -210,7 +266,13 @@ impl AddArgs {
     }
 
     /// Build dependencies from arguments
-    pub fn parse_dependencies(&self, manifest: &LocalManifest) -> CargoResult<Vec<Dependency>> {
+    ///
+    /// `workspace` is the manifest holding `[workspace.dependencies]`, if any.
+    pub fn parse_dependencies(
+        &self,
+        manifest: &LocalManifest,
+        workspace: Option<&LocalManifest>,
+    ) -> CargoResult<Vec<Dependency>> {
         let workspace_members = workspace_members(self.manifest_path.as_deref())?;
 
         if self.crates.len() > 1 && self.git.is_some() {
@@ -242,7 +304,12 @@ impl AddArgs {
                     anyhow::bail!("`+<feature>` must be preceded by a pkgid");
                 }
             } else {
-                let dep = self.parse_single_dependency(manifest, crate_spec, &workspace_members)?;
+                let dep = self.parse_single_dependency(
+                    manifest,
+                    workspace,
+                    crate_spec,
+                    &workspace_members,
+                )?;
                 deps.push(dep);
             }
         }
@@ -252,6 +319,7 @@ impl AddArgs {
     fn parse_single_dependency(
         &self,
         manifest: &LocalManifest,
+        workspace: Option<&LocalManifest>,
         crate_spec: &str,
         workspace_members: &[Package],
     ) -> CargoResult<Dependency> {
@@ -297,6 +365,16 @@ impl AddArgs {
                     self.get_existing_dependency(manifest, dependency.toml_key())
                 {
                     dependency = self.populate_dependency(old);
+                } else if self.registry.is_none()
+                    && self.registry_index.is_none()
+                    && workspace.is_some_and(|workspace| {
+                        workspace
+                            .get_workspace_dependency(dependency.toml_key())
+                            .is_ok()
+                    })
+                {
+                    // Reuse the requirement already declared for the whole workspace
+                    dependency = dependency.set_workspace();
                 } else if let Some(package) = workspace_members.iter().find(|p| p.name == *name) {
                     // Only special-case workspaces when the user doesn't provide any extra
                     // information, otherwise, trust the user.
@@ -315,9 +393,15 @@ impl AddArgs {
                         dependency = dependency.set_version(&v);
                     }
                 } else {
-                    let registry_url = registry_url(manifest_path, self.registry.as_deref())?;
-                    let latest =
-                        get_latest_dependency(name, false, manifest_path, Some(&registry_url))?;
+                    let registry_url = self.registry_url(manifest_path)?;
+                    let latest = get_latest_dependency(
+                        name,
+                        false,
+                        manifest_path,
+                        Some(&registry_url),
+                        self.offline,
+                        self.rust_version(manifest, workspace)?,
+                    )?;
 
                     let op = "";
                     let v = format!(
@@ -331,7 +415,8 @@ impl AddArgs {
                     dependency.name = latest.name; // Normalize the name
                     dependency = dependency
                         .set_version(&v)
-                        .set_available_features(latest.available_features);
+                        .set_available_features(latest.available_features)
+                        .set_available_dependencies(latest.available_dependencies);
                 }
 
                 dependency
@@ -369,7 +454,10 @@ impl AddArgs {
         if let Some(registry) = &self.registry {
             dependency = dependency.set_registry(registry);
         }
-        dependency = self.populate_available_features(dependency, manifest_path)?;
+        if let Some(registry_index) = &self.registry_index {
+            dependency = dependency.set_registry_index(registry_index);
+        }
+        dependency = self.populate_available_features(dependency, manifest)?;
 
         Ok(dependency)
     }
@@ -441,36 +529,90 @@ impl AddArgs {
         if let Some(ref rename) = self.rename {
             dependency = dependency.set_rename(rename);
         }
+        if let Some(public) = self.public() {
+            dependency = dependency.set_public(Some(public));
+        }
+        if let Some(artifact) = &self.artifact {
+            dependency = dependency
+                .set_artifact(Some(artifact.clone()))
+                .set_lib(self.lib.then_some(true));
+        }
 
         dependency
     }
 
+    /// The Rust version the added dependencies must support, that of the workspace when adding
+    /// to `[workspace.dependencies]`
+    fn rust_version(
+        &self,
+        manifest: &LocalManifest,
+        workspace: Option<&LocalManifest>,
+    ) -> CargoResult<Option<RustVersion>> {
+        if self.ignore_rust_version {
+            return Ok(None);
+        }
+        match workspace {
+            Some(workspace) if self.workspace_dep => match workspace.workspace_rust_version()? {
+                Some(rust_version) => Ok(Some(rust_version)),
+                None => workspace.rust_version(),
+            },
+            _ => manifest.rust_version(),
+        }
+    }
+
+    /// Resolve the index of the requested registry
+    fn registry_url(&self, manifest_path: &Path) -> CargoResult<Url> {
+        if let Some(registry_index) = &self.registry_index {
+            Url::parse(registry_index)
+                .with_context(|| format!("Invalid registry index URL `{}`", registry_index))
+        } else {
+            registry_url(manifest_path, self.registry.as_deref())
+        }
+    }
+
     /// Lookup available features
     pub fn populate_available_features(
         &self,
         dependency: Dependency,
-        manifest_path: &Path,
+        manifest: &LocalManifest,
     ) -> CargoResult<Dependency> {
         if !dependency.available_features.is_empty() {
             return Ok(dependency);
         }
 
-        let available_features = if let Some(path) = dependency.path() {
+        // Inherited dependencies expose the features of their `[workspace.dependencies]` entry
+        let source = manifest.resolve_workspace_dependency(dependency.clone())?;
+        let (available_features, available_dependencies) = if let Some(path) = source.path() {
             let manifest = get_manifest_from_path(path)?;
-            manifest.features()?
-        } else if let Some(repo) = dependency.git() {
-            get_manifest_from_url(repo)?
-                .map(|m| m.features())
-                .transpose()?
-                .unwrap_or_default()
-        } else if let Some(version) = dependency.version() {
-            let registry_url = registry_url(manifest_path, self.registry.as_deref())?;
-            get_features_from_registry(&dependency.name, version, &registry_url)?
+            (manifest.features()?, manifest.available_dependencies())
+        } else if let Some(repo) = source.git() {
+            match get_manifest_from_url(repo)? {
+                Some(manifest) => (manifest.features()?, manifest.available_dependencies()),
+                None => Default::default(),
+            }
+        } else if let Some(version) = source.version() {
+            let registry_url = if self.registry.is_some() || self.registry_index.is_some() {
+                self.registry_url(&manifest.path)?
+            } else if let Some(registry_index) = source.registry_index() {
+                Url::parse(registry_index)
+                    .with_context(|| format!("Invalid registry index URL `{}`", registry_index))?
+            } else {
+                registry_url(&manifest.path, source.registry())?
+            };
+            get_features_from_registry(
+                &source.name,
+                version,
+                &registry_url,
+                &manifest.path,
+                self.offline,
+            )?
         } else {
-            BTreeMap::new()
+            Default::default()
         };
 
-        let dependency = dependency.set_available_features(available_features);
+        let dependency = dependency
+            .set_available_features(available_features)
+            .set_available_dependencies(available_dependencies);
         Ok(dependency)
     }
 }
@@ -497,6 +639,10 @@ impl AddArgs {
     pub fn optional(&self) -> Option<bool> {
         resolve_bool_arg(self.optional, self.no_optional)
     }
+
+    pub fn public(&self) -> Option<bool> {
+        resolve_bool_arg(self.public, self.no_public)
+    }
 }
 
 #[cfg(test)]
@@ -514,15 +660,24 @@ impl Default for AddArgs {
             target: None,
             optional: false,
             no_optional: false,
+            in_feature: None,
             manifest_path: None,
             pkgid: None,
             features: None,
+            strict_features: false,
             no_default_features: false,
             default_features: false,
             dry_run: false,
             quiet: false,
             offline: true,
+            ignore_rust_version: false,
             registry: None,
+            registry_index: None,
+            public: false,
+            no_public: false,
+            artifact: None,
+            lib: false,
+            workspace_dep: false,
             unstable_features: vec![],
         }
     }
@@ -553,50 +708,95 @@ fn exec(mut args: AddArgs) -> CargoResult<()> {
         args.manifest_path = Some(pkg.manifest_path.into_std_path_buf());
     }
     let mut manifest = LocalManifest::find(args.manifest_path.as_deref())?;
+    // `[workspace.dependencies]` lives in the workspace root, which might be `manifest` itself
+    let workspace_root = manifest.workspace_root()?;
+    let mut workspace_manifest = match &workspace_root {
+        Some(root) if *root != manifest.path => Some(LocalManifest::try_new(root)?),
+        _ => None,
+    };
+    if args.workspace_dep && workspace_root.is_none() {
+        anyhow::bail!(
+            "`--workspace-dep` requires {} to be part of a workspace",
+            manifest.path.display()
+        );
+    }
 
-    if !args.offline && std::env::var("CARGO_IS_TEST").is_err() {
-        let url = registry_url(
-            &find(args.manifest_path.as_deref())?,
-            args.registry.as_ref().map(String::as_ref),
-        )?;
-        update_registry_index(&url, args.quiet)?;
+    args.offline = args.offline || is_offline(&manifest.path)?;
+    if !args.offline {
+        let url = args.registry_url(&manifest.path)?;
+        update_registry_index(&url, &manifest.path, args.quiet)?;
     }
 
-    let deps = &args.parse_dependencies(&manifest)?;
+    let workspace = workspace_root
+        .as_ref()
+        .map(|_| workspace_manifest.as_ref().unwrap_or(&manifest));
+    let deps = &args.parse_dependencies(&manifest, workspace)?;
 
     for dep in deps {
-        if let Some(req_feats) = dep.features.as_deref() {
-            let req_feats: BTreeSet<_> = req_feats.iter().map(|s| s.as_str()).collect();
-
-            let available_features = dep
-                .available_features
-                .keys()
-                .map(|s| s.as_ref())
-                .collect::<BTreeSet<&str>>();
-
-            let mut unknown_features: Vec<&&str> =
-                req_feats.difference(&available_features).collect();
-            unknown_features.sort();
-
-            if !unknown_features.is_empty() {
-                unrecognized_features_message(&format!(
-                    "Unrecognized features: {:?}",
-                    unknown_features
-                ))?;
-            };
+        let unknown_features = unknown_features(dep);
+        if unknown_features.is_empty() {
+            continue;
         }
+        let names: Vec<_> = unknown_features.iter().map(|(name, _)| name).collect();
+        let hints: Vec<_> = unknown_features
+            .iter()
+            .filter_map(|(_, hint)| hint.as_deref())
+            .collect();
+        if args.strict_features {
+            let mut message = format!("Unrecognized features: {:?}", names);
+            for hint in hints {
+                message.push_str("\n  ");
+                message.push_str(hint);
+            }
+            anyhow::bail!(message);
+        }
+        unrecognized_features_message(&format!("Unrecognized features: {:?}", names), &hints)?;
     }
 
-    let was_sorted = manifest
-        .get_table(&args.get_section())
-        .map(TomlItem::as_table)
-        .map_or(true, |table_option| {
-            table_option.map_or(true, |table| is_sorted(table.iter().map(|(name, _)| name)))
-        });
+    let section = args.get_section();
+    let workspace_section = vec!["workspace".to_owned(), "dependencies".to_owned()];
+    let was_sorted = is_table_sorted(&manifest, &section);
+    let workspace_was_sorted = is_table_sorted(
+        workspace_manifest.as_ref().unwrap_or(&manifest),
+        &workspace_section,
+    );
     deps.iter()
         .map(|dep| {
+            let dep = if args.workspace_dep && !dep.is_workspace() {
+                let workspace = workspace_manifest.as_mut().unwrap_or(&mut manifest);
+                let (workspace_dep, member_dep) = dep.split_workspace();
+                // An existing workspace entry is reused, as long as it has the requested version
+                match workspace.get_workspace_dependency(dep.toml_key()) {
+                    Ok(existing) => {
+                        if let Some(version) = workspace_dep
+                            .version()
+                            .filter(|version| existing.version() != Some(*version))
+                        {
+                            anyhow::bail!(
+                                "`{}` is already in `[workspace.dependencies]` with {}, not `{}`; \
+                                 change it there or leave the version out",
+                                dep.toml_key(),
+                                existing.version().map_or_else(
+                                    || "no version".to_owned(),
+                                    |existing| format!("version `{}`", existing)
+                                ),
+                                version
+                            );
+                        }
+                    }
+                    Err(_) => {
+                        if !args.quiet {
+                            print_msg(&workspace_dep, &workspace_section, false)?;
+                        }
+                        workspace.insert_into_table(&workspace_section, &workspace_dep)?;
+                    }
+                }
+                member_dep
+            } else {
+                dep.clone()
+            };
             if !args.quiet {
-                print_msg(dep, &args.get_section(), args.optional)?;
+                print_msg(&dep, &section, args.optional)?;
             }
             if let Some(path) = dep.path() {
                 if path == manifest.path.parent().unwrap_or_else(|| Path::new("")) {
@@ -606,8 +806,23 @@ fn exec(mut args: AddArgs) -> CargoResult<()> {
                     )
                 }
             }
-            manifest.insert_into_table(&args.get_section(), dep)?;
-            manifest.gc_dep(dep.toml_key());
+            manifest.insert_into_table(&section, &dep)?;
+            let cleanups = manifest.gc_dep(dep.toml_key())?;
+            if !args.quiet {
+                for cleanup in &cleanups {
+                    print_cleanup(cleanup)?;
+                }
+            }
+            if let Some(feature) = &args.in_feature {
+                let activation = format!("dep:{}", dep.toml_key());
+                let added = manifest.insert_feature(feature, &[&activation])?;
+                if !args.quiet && !added.is_empty() {
+                    print_feature_msg(&activation, feature)?;
+                }
+            }
+            if args.optional() == Some(true) && manifest.exposes_implicit_feature(dep.toml_key()) {
+                implicit_feature_message(dep.toml_key())?;
+            }
             Ok(())
         })
         .collect::<CargoResult<Vec<_>>>()
@@ -617,19 +832,25 @@ fn exec(mut args: AddArgs) -> CargoResult<()> {
         })?;
 
     if was_sorted {
-        if let Some(table) = manifest
-            .get_table_mut(&args.get_section())
-            .ok()
-            .and_then(TomlItem::as_table_like_mut)
-        {
-            table.sort_values();
-        }
+        sort_table(&mut manifest, &section);
+    }
+    if args.workspace_dep && workspace_was_sorted {
+        sort_table(
+            workspace_manifest.as_mut().unwrap_or(&mut manifest),
+            &workspace_section,
+        );
     }
 
     if args.dry_run {
         dry_run_message()?;
     } else {
-        manifest.write()?;
+        // The workspace root and the package are updated together or not at all
+        let mut transaction = ManifestTransaction::new();
+        if let (true, Some(workspace_manifest)) = (args.workspace_dep, &workspace_manifest) {
+            transaction.stage_workspace(workspace_manifest)?;
+        }
+        transaction.stage(&manifest)?;
+        transaction.commit()?;
     }
 
     Ok(())
@@ -644,6 +865,8 @@ fn print_msg(dep: &Dependency, section: &[String], optional: bool) -> CargoResul
     write!(output, " {}", dep.name)?;
     if dep.path().is_some() {
         write!(output, " (local)")?;
+    } else if dep.is_workspace() {
+        write!(output, " (workspace)")?;
     } else if let Some(version) = dep.version() {
         if version.chars().next().unwrap_or('0').is_ascii_digit() {
             write!(output, " v{}", version)?;
@@ -657,6 +880,8 @@ fn print_msg(dep: &Dependency, section: &[String], optional: bool) -> CargoResul
     }
     let section = if section.len() == 1 {
         section[0].clone()
+    } else if section[0] == "workspace" {
+        format!("workspace {}", section[1])
     } else {
         format!("{} for target `{}`", &section[2], &section[1])
     };
@@ -711,6 +936,25 @@ fn print_msg(dep: &Dependency, section: &[String], optional: bool) -> CargoResul
     Ok(())
 }
 
+fn is_table_sorted(manifest: &LocalManifest, section: &[String]) -> bool {
+    manifest
+        .get_table(section)
+        .map(TomlItem::as_table)
+        .map_or(true, |table_option| {
+            table_option.is_none_or(|table| is_sorted(table.iter().map(|(name, _)| name)))
+        })
+}
+
+fn sort_table(manifest: &mut LocalManifest, section: &[String]) {
+    if let Some(table) = manifest
+        .get_table_mut(section)
+        .ok()
+        .and_then(TomlItem::as_table_like_mut)
+    {
+        table.sort_values();
+    }
+}
+
 // Based on Iterator::is_sorted from nightly std; remove in favor of that when stabilized.
 fn is_sorted(mut it: impl Iterator<Item = impl PartialOrd>) -> bool {
     let mut last = match it.next() {
@@ -728,7 +972,7 @@ fn is_sorted(mut it: impl Iterator<Item = impl PartialOrd>) -> bool {
     true
 }
 
-fn unrecognized_features_message(message: &str) -> CargoResult<()> {
+fn unrecognized_features_message(message: &str, hints: &[&str]) -> CargoResult<()> {
     let colorchoice = colorize_stderr();
     let mut output = StandardStream::stderr(colorchoice);
     output.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
@@ -736,6 +980,192 @@ fn unrecognized_features_message(message: &str) -> CargoResult<()> {
     output.reset()?;
     writeln!(output, " {}", message)
         .with_context(|| "Failed to write unrecognized features message")?;
+    for hint in hints {
+        writeln!(output, "{:>12} {}", "", hint)
+            .with_context(|| "Failed to write unrecognized features message")?;
+    }
+    Ok(())
+}
+
+/// Requested features not exposed by the dependency, along with a hint at what was meant
+///
+/// Besides its own features, the `dep/feature` and `dep:dep` forms are checked against the
+/// dependencies of the dependency as Cargo only accepts them in the `[features]` table.
+fn unknown_features(dep: &Dependency) -> Vec<(&str, Option<String>)> {
+    let mut unknown = dep
+        .features
+        .iter()
+        .flatten()
+        .filter_map(|feature| {
+            let hint = if let Some(name) = feature.strip_prefix("dep:") {
+                Some(optional_dependency_hint(dep, feature, name))
+            } else if let Some((name, _)) = feature.split_once('/') {
+                Some(dependency_feature_hint(
+                    dep,
+                    feature,
+                    name.trim_end_matches('?'),
+                ))
+            } else if dep.available_features.contains_key(feature) {
+                return None;
+            } else {
+                closest_match(feature, dep.available_features.keys().map(String::as_str))
+                    .map(|close| format!("`{}`: did you mean `{}`?", feature, close))
+            };
+            Some((feature.as_str(), hint))
+        })
+        .collect::<Vec<_>>();
+    unknown.sort();
+    unknown
+}
+
+/// Explain why `dep:<name>` can't be requested and what to request instead
+fn optional_dependency_hint(dep: &Dependency, feature: &str, name: &str) -> String {
+    if dep.available_dependencies.get(name) != Some(&true) {
+        let optional = dep
+            .available_dependencies
+            .iter()
+            .filter(|(_, optional)| **optional)
+            .map(|(name, _)| name.as_str());
+        match closest_match(name, optional) {
+            Some(close) => format!(
+                "`{}`: `{}` is not an optional dependency of `{}`, did you mean `{}`?",
+                feature, name, dep.name, close
+            ),
+            None => format!(
+                "`{}`: `{}` is not an optional dependency of `{}`",
+                feature, name, dep.name
+            ),
+        }
+    } else if dep.available_features.contains_key(name) {
+        format!(
+            "`{}`: optional dependencies are enabled by their name, did you mean `{}`?",
+            feature, name
+        )
+    } else if let Some(enabling) = enabling_feature(dep, &[feature.to_owned()]) {
+        format!(
+            "`{}`: `{}` is only enabled through features of `{}`, did you mean `{}`?",
+            feature, name, dep.name, enabling
+        )
+    } else {
+        format!(
+            "`{}`: `{}` has no feature enabling `{}`",
+            feature, dep.name, name
+        )
+    }
+}
+
+/// Explain why `<name>/<feature>` can't be requested and what to request instead
+fn dependency_feature_hint(dep: &Dependency, feature: &str, name: &str) -> String {
+    let dep_feature = feature.split_once('/').map(|(_, f)| f).unwrap_or_default();
+    if !dep.available_dependencies.contains_key(name) {
+        let dependencies = dep.available_dependencies.keys().map(String::as_str);
+        match closest_match(name, dependencies) {
+            Some(close) => format!(
+                "`{}`: `{}` is not a dependency of `{}`, did you mean `{}/{}`?",
+                feature, name, dep.name, close, dep_feature
+            ),
+            None => format!(
+                "`{}`: `{}` is not a dependency of `{}`",
+                feature, name, dep.name
+            ),
+        }
+    } else if let Some(enabling) = enabling_feature(
+        dep,
+        &[
+            format!("{}/{}", name, dep_feature),
+            format!("{}?/{}", name, dep_feature),
+        ],
+    ) {
+        format!(
+            "`{}`: features of dependencies are enabled through features of `{}`, did you mean `{}`?",
+            feature, dep.name, enabling
+        )
+    } else {
+        format!(
+            "`{}`: features of dependencies are enabled through features of `{}`, but none enables `{}/{}`",
+            feature, dep.name, name, dep_feature
+        )
+    }
+}
+
+/// Find a feature of `dep` activating any of `values`
+fn enabling_feature<'d>(dep: &'d Dependency, values: &[String]) -> Option<&'d str> {
+    dep.available_features
+        .iter()
+        .find(|(_, activations)| activations.iter().any(|a| values.contains(a)))
+        .map(|(name, _)| name.as_str())
+}
+
+fn print_feature_msg(activation: &str, feature: &str) -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+    write!(output, "{:>12}", "Adding")?;
+    output.reset()?;
+    writeln!(output, " {} to feature `{}`.", activation, feature)?;
+    Ok(())
+}
+
+fn print_cleanup(cleanup: &FeatureCleanup) -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+    match cleanup {
+        FeatureCleanup::Removed {
+            feature,
+            activation,
+        } => {
+            write!(output, "{:>12}", "Removing")?;
+            output.reset()?;
+            writeln!(output, " {} from feature `{}`.", activation, feature)?;
+        }
+        FeatureCleanup::Rewritten {
+            feature,
+            activation,
+            replacement,
+        } => {
+            write!(output, "{:>12}", "Updating")?;
+            output.reset()?;
+            writeln!(
+                output,
+                " {} to {} in feature `{}`.",
+                activation, replacement, feature
+            )?;
+        }
+        FeatureCleanup::FeatureRemoved {
+            feature,
+            references,
+        } => {
+            write!(output, "{:>12}", "Removing")?;
+            output.reset()?;
+            writeln!(
+                output,
+                " feature `{}`, nothing is left for it to activate.",
+                feature
+            )?;
+            for reference in references {
+                output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+                write!(output, "{:>12}", "Removing")?;
+                output.reset()?;
+                writeln!(output, " {} from {}.", feature, reference)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn implicit_feature_message(dep_key: &str) -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+    write!(output, "{:>12}", "Warning:")?;
+    output.reset()?;
+    writeln!(
+        output,
+        " `{}` is exposed as a feature, use `--in-feature <FEATURE>` to enable it with `dep:{}` instead",
+        dep_key, dep_key
+    )
+    .with_context(|| "Failed to write implicit feature message")?;
     Ok(())
 }
 
@@ -0,0 +1,446 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use cargo_edit_9::{
+    closest_match, colorize_stderr, manifest_from_pkgid, CargoResult, Context, LocalManifest,
+};
+use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
+
+/// Manage the `[features]` table of a Cargo.toml manifest file.
+#[derive(Debug, clap::Args)]
+#[clap(version)]
+#[clap(after_help = "\
+Features can activate other features, like `std`, optional dependencies, like `dep:serde`, and \
+features of dependencies, like `serde/derive` or `serde?/derive`. Activations are checked against \
+the features and dependencies of the package before the manifest is written.")]
+pub struct FeatureArgs {
+    #[clap(subcommand)]
+    command: FeatureCommand,
+}
+
+impl FeatureArgs {
+    pub fn exec(self) -> CargoResult<()> {
+        match self.command {
+            FeatureCommand::Add(args) => add(args),
+            FeatureCommand::Rm(args) => rm(args),
+            FeatureCommand::Ls(args) => ls(args),
+            FeatureCommand::SetDefault(args) => set_default(args),
+        }
+    }
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum FeatureCommand {
+    /// Add a feature, or activations to an existing one
+    Add(AddArgs),
+    /// Remove features, along with the references to them
+    Rm(RmArgs),
+    /// List features and everything they activate
+    Ls(LsArgs),
+    /// Set the features enabled by default
+    SetDefault(SetDefaultArgs),
+}
+
+#[derive(Debug, clap::Args)]
+struct AddArgs {
+    /// Name of the feature
+    #[clap(value_name = "NAME")]
+    name: String,
+
+    /// Features, optional dependencies or features of dependencies to activate
+    #[clap(value_name = "ACTIVATION")]
+    activations: Vec<String>,
+
+    #[clap(flatten)]
+    manifest: ManifestArgs,
+
+    /// Don't actually write the manifest
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Do not print any output in case of success.
+    #[clap(long, short)]
+    quiet: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct RmArgs {
+    /// Features to remove
+    #[clap(value_name = "NAME", required = true)]
+    names: Vec<String>,
+
+    #[clap(flatten)]
+    manifest: ManifestArgs,
+
+    /// Don't actually write the manifest
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Do not print any output in case of success.
+    #[clap(long, short)]
+    quiet: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct LsArgs {
+    #[clap(flatten)]
+    manifest: ManifestArgs,
+}
+
+#[derive(Debug, clap::Args)]
+struct SetDefaultArgs {
+    /// Features to enable by default, none to remove the `default` feature
+    #[clap(value_name = "FEATURE")]
+    features: Vec<String>,
+
+    #[clap(flatten)]
+    manifest: ManifestArgs,
+
+    /// Don't actually write the manifest
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Do not print any output in case of success.
+    #[clap(long, short)]
+    quiet: bool,
+}
+
+/// Which manifest to edit
+#[derive(Debug, clap::Args)]
+struct ManifestArgs {
+    /// Path to `Cargo.toml`
+    #[clap(
+        long,
+        value_name = "PATH",
+        parse(from_os_str),
+        conflicts_with = "pkgid"
+    )]
+    manifest_path: Option<PathBuf>,
+
+    /// Package id of the crate to edit the features of
+    #[clap(long = "package", short = 'p', value_name = "PKGID")]
+    pkgid: Option<String>,
+}
+
+impl ManifestArgs {
+    fn open(&self) -> CargoResult<LocalManifest> {
+        if let Some(pkgid) = &self.pkgid {
+            let package = manifest_from_pkgid(self.manifest_path.as_deref(), pkgid)?;
+            LocalManifest::try_new(package.manifest_path.as_std_path())
+        } else {
+            LocalManifest::find(self.manifest_path.as_deref())
+        }
+    }
+}
+
+fn add(args: AddArgs) -> CargoResult<()> {
+    let mut manifest = args.manifest.open()?;
+    check_feature_name(&args.name)?;
+
+    let is_new = !explicit_features(&manifest).contains_key(&args.name);
+    let activations: Vec<_> = args.activations.iter().map(String::as_str).collect();
+    let added = manifest.insert_feature(&args.name, &activations)?;
+
+    for activation in &added {
+        check_activation(&manifest, &args.name, activation)?;
+    }
+    check_optional_dependency(&manifest, &args.name)?;
+
+    if !args.quiet {
+        if is_new {
+            print_msg("Adding", &args.name, "to", "features")?;
+        } else {
+            print_msg("Updating", &args.name, "in", "features")?;
+        }
+        print_activations(&added)?;
+    }
+
+    if args.dry_run {
+        dry_run_message()?;
+    } else {
+        manifest.write()?;
+    }
+
+    Ok(())
+}
+
+fn rm(args: RmArgs) -> CargoResult<()> {
+    let mut manifest = args.manifest.open()?;
+
+    for name in &args.names {
+        if !args.quiet {
+            print_msg("Removing", name, "from", "features")?;
+        }
+        let references = manifest.remove_feature(name).map_err(|err| {
+            eprintln!("Could not edit `Cargo.toml`.\n\nERROR: {}", err);
+            err
+        })?;
+        if !args.quiet {
+            for reference in references {
+                print_msg("Removing", name, "from", &reference)?;
+            }
+        }
+    }
+
+    if args.dry_run {
+        dry_run_message()?;
+    } else {
+        manifest.write()?;
+    }
+
+    Ok(())
+}
+
+fn ls(args: LsArgs) -> CargoResult<()> {
+    let manifest = args.manifest.open()?;
+
+    // Spell out what the implicit features of optional dependencies activate
+    let explicit = explicit_features(&manifest);
+    let features: BTreeMap<_, _> = manifest
+        .features()?
+        .into_iter()
+        .map(|(name, activations)| {
+            if explicit.contains_key(&name) {
+                (name, activations)
+            } else {
+                let activations = vec![format!("dep:{}", name)];
+                (name, activations)
+            }
+        })
+        .collect();
+
+    for (name, activations) in &features {
+        println!("{}", name);
+        print_tree(&features, activations, "", &mut vec![name.as_str()]);
+    }
+
+    Ok(())
+}
+
+fn set_default(args: SetDefaultArgs) -> CargoResult<()> {
+    let mut manifest = args.manifest.open()?;
+
+    if args.features.is_empty() {
+        if !args.quiet {
+            print_msg("Removing", "default", "from", "features")?;
+        }
+        manifest.remove_feature("default")?;
+    } else {
+        if !manifest.data.contains_key("features") {
+            manifest.data["features"] = toml_edit::table();
+        }
+        let default: toml_edit::Array = args.features.iter().map(String::as_str).collect();
+        manifest.data["features"]["default"] = toml_edit::value(default);
+        for feature in &args.features {
+            check_activation(&manifest, "default", feature)?;
+        }
+
+        if !args.quiet {
+            print_msg("Setting", "default", "in", "features")?;
+            print_activations(&args.features.iter().map(String::as_str).collect::<Vec<_>>())?;
+        }
+    }
+
+    if args.dry_run {
+        dry_run_message()?;
+    } else {
+        manifest.write()?;
+    }
+
+    Ok(())
+}
+
+/// Features as written in `[features]`, without the implicit ones of optional dependencies
+fn explicit_features(manifest: &LocalManifest) -> BTreeMap<String, toml_edit::Item> {
+    manifest
+        .data
+        .get("features")
+        .and_then(toml_edit::Item::as_table_like)
+        .into_iter()
+        .flat_map(|features| features.iter())
+        .map(|(name, item)| (name.to_owned(), item.clone()))
+        .collect()
+}
+
+/// Cargo only accepts alphanumerics, `_`, `-`, `+` and `.` in feature names
+fn check_feature_name(name: &str) -> CargoResult<()> {
+    let mut chars = name.chars();
+    let valid_start = chars
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_');
+    if !valid_start || !chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '+' | '.')) {
+        anyhow::bail!("Invalid feature name `{}`", name);
+    }
+    Ok(())
+}
+
+/// A feature named after an optional dependency replaces its implicit feature, which requires
+/// enabling the dependency explicitly
+fn check_optional_dependency(manifest: &LocalManifest, name: &str) -> CargoResult<()> {
+    let is_optional = manifest.available_dependencies().get(name) == Some(&true);
+    let enabled = format!("dep:{}", name);
+    if is_optional
+        && !manifest
+            .features()?
+            .values()
+            .flatten()
+            .any(|activation| *activation == enabled)
+    {
+        anyhow::bail!(
+            "The feature `{}` has the name of an optional dependency, add `{}` to one of the features to enable it",
+            name,
+            enabled
+        );
+    }
+    Ok(())
+}
+
+/// Ensure `feature` can activate `activation`
+fn check_activation(manifest: &LocalManifest, feature: &str, activation: &str) -> CargoResult<()> {
+    let features = manifest.features()?;
+    let dependencies = manifest.available_dependencies();
+    let optional = || {
+        dependencies
+            .iter()
+            .filter(|(_, optional)| **optional)
+            .map(|(name, _)| name.as_str())
+    };
+
+    let reason = if let Some(name) = activation.strip_prefix("dep:") {
+        (dependencies.get(name) != Some(&true)).then(|| {
+            format!(
+                "`{}` is not an optional dependency{}",
+                name,
+                did_you_mean(closest_match(name, optional()).map(|close| format!("dep:{}", close)))
+            )
+        })
+    } else if let Some((name, dep_feature)) = activation.split_once('/') {
+        let (name, weak) = match name.strip_suffix('?') {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        match dependencies.get(name) {
+            None => Some(format!(
+                "`{}` is not a dependency{}",
+                name,
+                did_you_mean(
+                    closest_match(name, dependencies.keys().map(String::as_str)).map(
+                        |close| format!("{}{}/{}", close, if weak { "?" } else { "" }, dep_feature)
+                    )
+                )
+            )),
+            Some(false) if weak => Some(format!(
+                "`{}` is not an optional dependency{}",
+                name,
+                did_you_mean(Some(format!("{}/{}", name, dep_feature)))
+            )),
+            Some(_) => None,
+        }
+    } else if activation == feature {
+        Some("a feature cannot activate itself".to_owned())
+    } else if features.contains_key(activation) {
+        None
+    } else {
+        match dependencies.get(activation) {
+            Some(true) => Some(format!(
+                "`{}` is an optional dependency without an implicit feature{}",
+                activation,
+                did_you_mean(Some(format!("dep:{}", activation)))
+            )),
+            Some(false) => Some(format!(
+                "`{}` is not an optional dependency, enable features of it with `{}/<feature>`",
+                activation, activation
+            )),
+            None => Some(format!(
+                "`{}` is neither a feature nor an optional dependency{}",
+                activation,
+                did_you_mean(closest_match(
+                    activation,
+                    features.keys().map(String::as_str)
+                ))
+            )),
+        }
+    };
+
+    if let Some(reason) = reason {
+        anyhow::bail!(
+            "Feature `{}` cannot activate `{}`: {}",
+            feature,
+            activation,
+            reason
+        );
+    }
+    Ok(())
+}
+
+fn did_you_mean(suggestion: Option<impl std::fmt::Display>) -> String {
+    suggestion
+        .map(|suggestion| format!(", did you mean `{}`?", suggestion))
+        .unwrap_or_default()
+}
+
+/// Print what `activations` enable, resolving features recursively
+fn print_tree<'f>(
+    features: &'f BTreeMap<String, Vec<String>>,
+    activations: &'f [String],
+    prefix: &str,
+    path: &mut Vec<&'f str>,
+) {
+    for (i, activation) in activations.iter().enumerate() {
+        let (branch, indent) = if i + 1 == activations.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        match features.get(activation) {
+            Some(_) if path.contains(&activation.as_str()) => {
+                println!("{}{}{} (cycle)", prefix, branch, activation);
+            }
+            Some(nested) => {
+                println!("{}{}{}", prefix, branch, activation);
+                path.push(activation);
+                print_tree(features, nested, &format!("{}{}", prefix, indent), path);
+                path.pop();
+            }
+            None => println!("{}{}{}", prefix, branch, activation),
+        }
+    }
+}
+
+fn print_msg(action: &str, name: &str, preposition: &str, table: &str) -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+    write!(output, "{:>12}", action)?;
+    output.reset()?;
+    writeln!(output, " {} {} {}.", name, preposition, table)?;
+    Ok(())
+}
+
+fn print_activations(activations: &[&str]) -> CargoResult<()> {
+    if activations.is_empty() {
+        return Ok(());
+    }
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    writeln!(output, "{:>13}Activations:", " ")?;
+    for activation in activations {
+        output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        write!(output, "{:>13}+ ", " ")?;
+        output.reset()?;
+        writeln!(output, "{}", activation)?;
+    }
+    Ok(())
+}
+
+fn dry_run_message() -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+    write!(output, "{:>12}", "Warning:")?;
+    output.reset()?;
+    writeln!(output, " aborting feature due to dry run")
+        .with_context(|| "Failed to write dry run message")?;
+    Ok(())
+}
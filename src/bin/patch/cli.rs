@@ -0,0 +1,22 @@
+use cargo_edit_9::CargoResult;
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+#[clap(bin_name = "cargo")]
+pub enum Command {
+    Patch(crate::patch::PatchArgs),
+}
+
+impl Command {
+    pub fn exec(self) -> CargoResult<()> {
+        match self {
+            Self::Patch(patch) => patch.exec(),
+        }
+    }
+}
+
+#[test]
+fn verify_app() {
+    use clap::CommandFactory;
+    Command::command().debug_assert()
+}
@@ -0,0 +1,398 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use cargo_edit_9::{
+    colorize_stderr, registry_url, CargoResult, Context, CrateSpec, Dependency, LocalManifest,
+    ManifestKind,
+};
+use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
+use url::Url;
+
+const CRATES_IO: &str = "crates-io";
+
+/// Override dependencies with `[patch]` or `[replace]` in the workspace root manifest.
+#[derive(Debug, clap::Args)]
+#[clap(version)]
+#[clap(after_help = "\
+Patches always live in the root manifest of the workspace, even when running from a member.
+
+By default, crates from crates.io are patched. Use `--registry` to patch crates from an \
+alternative registry or `--source` to patch crates from any other source, e.g. a git repository.")]
+pub struct PatchArgs {
+    #[clap(subcommand)]
+    command: PatchCommand,
+}
+
+impl PatchArgs {
+    pub fn exec(self) -> CargoResult<()> {
+        match self.command {
+            PatchCommand::Add(args) => add(args),
+            PatchCommand::Rm(args) => rm(args),
+            PatchCommand::Ls(args) => ls(args),
+        }
+    }
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum PatchCommand {
+    /// Patch a crate with a local checkout or a git repository
+    Add(AddArgs),
+    /// Remove patches
+    Rm(RmArgs),
+    /// List patches
+    Ls(LsArgs),
+}
+
+#[derive(Debug, clap::Args)]
+struct AddArgs {
+    /// Crate to patch
+    ///
+    /// Either a name, like `serde`, with an optional version, like `serde@1.0.136`, or the path
+    /// to a local checkout.
+    #[clap(value_name = "CRATE")]
+    crate_spec: String,
+
+    /// Local checkout to patch the crate with
+    #[clap(long, value_name = "PATH", parse(from_os_str), conflicts_with = "git")]
+    path: Option<PathBuf>,
+
+    /// Git repository to patch the crate with
+    #[clap(long, value_name = "URI")]
+    git: Option<String>,
+
+    /// Git branch to download the crate from.
+    #[clap(long, value_name = "BRANCH", requires = "git", group = "git-ref")]
+    branch: Option<String>,
+
+    /// Git tag to download the crate from.
+    #[clap(long, value_name = "TAG", requires = "git", group = "git-ref")]
+    tag: Option<String>,
+
+    /// Git reference to download the crate from
+    #[clap(long, value_name = "REV", requires = "git", group = "git-ref")]
+    rev: Option<String>,
+
+    #[clap(flatten)]
+    source: SourceArgs,
+
+    /// Path to `Cargo.toml`
+    #[clap(long, value_name = "PATH", parse(from_os_str))]
+    manifest_path: Option<PathBuf>,
+
+    /// Don't actually write the manifest
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Do not print any output in case of success.
+    #[clap(long, short)]
+    quiet: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct RmArgs {
+    /// Crates to stop patching.
+    #[clap(value_name = "CRATE", required = true)]
+    crates: Vec<String>,
+
+    #[clap(flatten)]
+    source: SourceArgs,
+
+    /// Path to `Cargo.toml`
+    #[clap(long, value_name = "PATH", parse(from_os_str))]
+    manifest_path: Option<PathBuf>,
+
+    /// Don't actually write the manifest
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Do not print any output in case of success.
+    #[clap(long, short)]
+    quiet: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct LsArgs {
+    /// Path to `Cargo.toml`
+    #[clap(long, value_name = "PATH", parse(from_os_str))]
+    manifest_path: Option<PathBuf>,
+}
+
+/// Which table the patch lives in
+#[derive(Debug, clap::Args)]
+struct SourceArgs {
+    /// Patch crates from this registry, as named in the cargo config
+    #[clap(long, value_name = "NAME", conflicts_with = "source")]
+    registry: Option<String>,
+
+    /// Patch crates from this source URL, e.g. a git repository
+    #[clap(long, value_name = "URL")]
+    source: Option<String>,
+
+    /// Use the deprecated `[replace]` table instead of `[patch]`
+    ///
+    /// Replacements apply to a single version, so the crate must be given as `<name>@<version>`.
+    #[clap(long, conflicts_with = "registry", conflicts_with = "source")]
+    replace: bool,
+}
+
+impl SourceArgs {
+    /// Resolve the table holding the patches, like `patch.crates-io`
+    fn table_path(&self, manifest_path: &Path) -> CargoResult<Vec<String>> {
+        if self.replace {
+            return Ok(vec!["replace".to_owned()]);
+        }
+
+        let source = if let Some(registry) = self.registry.as_deref() {
+            if registry != CRATES_IO {
+                // Only registries known to cargo can be patched
+                registry_url(manifest_path, Some(registry))?;
+            }
+            registry.to_owned()
+        } else if let Some(source) = self.source.as_deref() {
+            Url::parse(source).with_context(|| format!("Invalid source URL `{}`", source))?;
+            source.to_owned()
+        } else {
+            CRATES_IO.to_owned()
+        };
+        Ok(vec!["patch".to_owned(), source])
+    }
+}
+
+fn add(args: AddArgs) -> CargoResult<()> {
+    let mut root = find_root(args.manifest_path.as_deref())?;
+    let table_path = args.source.table_path(&root.path)?;
+
+    let crate_spec = CrateSpec::resolve(&args.crate_spec)?;
+    let mut dep = crate_spec.to_dependency()?;
+    let key = match &crate_spec {
+        CrateSpec::PkgId { name, version_req } => {
+            if let Some(path) = &args.path {
+                dep = dep.set_path(dunce::canonicalize(path)?);
+            } else if let Some(repo) = &args.git {
+                if let Some(version_req) = version_req {
+                    anyhow::bail!(
+                        "Cannot specify a git URL (`{}`) with a version (`{}`).",
+                        repo,
+                        version_req
+                    );
+                }
+                dep = dep.set_git(
+                    repo,
+                    args.branch.clone(),
+                    args.tag.clone(),
+                    args.rev.clone(),
+                );
+            } else {
+                anyhow::bail!("Specify `--path` or `--git` to patch `{}` with", name);
+            }
+
+            if args.source.replace {
+                dep = dep.clear_version();
+                replace_key(name, version_req.as_deref())?
+            } else {
+                name.clone()
+            }
+        }
+        CrateSpec::Path(path) => {
+            if args.path.is_some() || args.git.is_some() {
+                anyhow::bail!(
+                    "Cannot specify `--path` or `--git` with a path (`{}`)",
+                    path.display()
+                );
+            }
+            if args.source.replace {
+                anyhow::bail!("`--replace` requires a crate given as `<name>@<version>`");
+            }
+            dep.name.clone()
+        }
+    };
+
+    if !args.quiet {
+        print_msg("Adding", &key, Some(&dep), "to", &table_path)?;
+    }
+    ensure_implicit_parent(&mut root, &table_path);
+    root.insert_into_table_named(&table_path, &key, &dep)?;
+
+    if args.dry_run {
+        dry_run_message()?;
+    } else {
+        write_root(&root)?;
+    }
+
+    Ok(())
+}
+
+fn rm(args: RmArgs) -> CargoResult<()> {
+    let mut root = find_root(args.manifest_path.as_deref())?;
+    let table_path = args.source.table_path(&root.path)?;
+
+    for crate_spec in &args.crates {
+        let keys = if args.source.replace {
+            match CrateSpec::resolve(crate_spec)? {
+                CrateSpec::PkgId {
+                    name,
+                    version_req: Some(version_req),
+                } => vec![replace_key(&name, Some(&version_req))?],
+                // Without a version, drop every replacement of the crate
+                CrateSpec::PkgId {
+                    name,
+                    version_req: None,
+                } => {
+                    let prefix = format!("{}:", name);
+                    let keys = root
+                        .get_table(&table_path)
+                        .ok()
+                        .and_then(|table| table.as_table_like())
+                        .into_iter()
+                        .flat_map(|table| table.iter())
+                        .map(|(key, _)| key.to_owned())
+                        .filter(|key| *key == name || key.starts_with(&prefix))
+                        .collect::<Vec<_>>();
+                    if keys.is_empty() {
+                        vec![name]
+                    } else {
+                        keys
+                    }
+                }
+                CrateSpec::Path(path) => {
+                    anyhow::bail!("Invalid name: {}", path.display())
+                }
+            }
+        } else {
+            vec![crate_spec.clone()]
+        };
+
+        for key in keys {
+            if !args.quiet {
+                print_msg("Removing", &key, None, "from", &table_path)?;
+            }
+            root.remove_from_table_path(&table_path, &key)
+                .map_err(|err| {
+                    eprintln!("Could not edit `Cargo.toml`.\n\nERROR: {}", err);
+                    err
+                })?;
+        }
+    }
+
+    if args.dry_run {
+        dry_run_message()?;
+    } else {
+        write_root(&root)?;
+    }
+
+    Ok(())
+}
+
+fn ls(args: LsArgs) -> CargoResult<()> {
+    let root = find_root(args.manifest_path.as_deref())?;
+
+    let patches = root
+        .data
+        .get("patch")
+        .and_then(|patch| patch.as_table_like())
+        .into_iter()
+        .flat_map(|patch| patch.iter())
+        .filter_map(|(source, table)| {
+            Some((
+                format!("patch.{}", toml_edit::Key::new(source)),
+                table.as_table_like()?,
+            ))
+        });
+    let replacements = root
+        .data
+        .get("replace")
+        .and_then(|replace| replace.as_table_like())
+        .map(|table| ("replace".to_owned(), table));
+
+    for (table_name, table) in patches.chain(replacements) {
+        println!("{}:", table_name);
+        for (key, item) in table.iter() {
+            let value = item
+                .clone()
+                .into_value()
+                .map_err(|_| anyhow::format_err!("Invalid patch {}.{}", table_name, key))?;
+            println!(
+                "    {} = {}",
+                toml_edit::Key::new(key),
+                value.to_string().trim()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Patches apply to the whole workspace, so they only take effect in its root manifest
+fn find_root(manifest_path: Option<&Path>) -> CargoResult<LocalManifest> {
+    let manifest = LocalManifest::find(manifest_path)?;
+    match manifest.workspace_root()? {
+        Some(root) if root != manifest.path => LocalManifest::try_new(&root),
+        _ => Ok(manifest),
+    }
+}
+
+fn write_root(root: &LocalManifest) -> CargoResult<()> {
+    if root.kind().is_some_and(ManifestKind::is_workspace) {
+        root.write_workspace()
+    } else {
+        root.write()
+    }
+}
+
+/// Avoid writing an empty `[patch]` header above `[patch.<source>]`
+fn ensure_implicit_parent(root: &mut LocalManifest, table_path: &[String]) {
+    if table_path.len() > 1 && !root.data.contains_key(&table_path[0]) {
+        let mut parent = toml_edit::Table::new();
+        parent.set_implicit(true);
+        root.data[&table_path[0]] = toml_edit::Item::Table(parent);
+    }
+}
+
+/// `[replace]` is keyed by package ID, like `serde:1.0.136`
+fn replace_key(name: &str, version_req: Option<&str>) -> CargoResult<String> {
+    let version = version_req
+        .map(|req| req.trim_start_matches('='))
+        .and_then(|req| semver::Version::parse(req).ok())
+        .with_context(|| {
+            format!(
+                "`--replace` requires an exact version, like `{}@1.0.0`",
+                name
+            )
+        })?;
+    Ok(format!("{}:{}", name, version))
+}
+
+fn print_msg(
+    action: &str,
+    key: &str,
+    dep: Option<&Dependency>,
+    preposition: &str,
+    table_path: &[String],
+) -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+    write!(output, "{:>12}", action)?;
+    output.reset()?;
+    write!(output, " {}", key)?;
+    if let Some(dep) = dep {
+        if dep.path().is_some() {
+            write!(output, " (local)")?;
+        } else if let Some(repo) = dep.git() {
+            write!(output, " ({})", repo)?;
+        }
+    }
+    writeln!(output, " {} {}.", preposition, table_path.join("."))?;
+    Ok(())
+}
+
+fn dry_run_message() -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+    write!(output, "{:>12}", "Warning:")?;
+    output.reset()?;
+    writeln!(output, " aborting patch due to dry run")
+        .with_context(|| "Failed to write dry run message")?;
+    Ok(())
+}
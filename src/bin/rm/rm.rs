@@ -1,5 +1,8 @@
-use cargo_edit_9::CargoResult;
-use cargo_edit_9::{colorize_stderr, manifest_from_pkgid, LocalManifest};
+use cargo_edit_9::{
+    colorize_stderr, find_unused_dependencies, manifest_from_pkgid, resolve_workspace,
+    workspace_members, FeatureCleanup, LocalManifest, ManifestTransaction, UnusedDependency,
+};
+use cargo_edit_9::{CargoResult, Context};
 use clap::Args;
 use std::borrow::Cow;
 use std::io::Write;
@@ -11,9 +14,24 @@ use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
 #[clap(version)]
 pub struct RmArgs {
     /// Crates to be removed.
-    #[clap(value_name = "CRATE", required = true)]
+    #[clap(value_name = "CRATE", required_unless_present = "unused")]
     crates: Vec<String>,
 
+    /// Remove the dependencies that no target of the package refers to.
+    ///
+    /// Sources are searched for `use`, `extern crate` and paths starting with the crate name.
+    /// Dependencies only used by tests, examples, benchmarks or the build script are reported
+    /// as belonging to another section instead of being removed.
+    #[clap(
+        long,
+        conflicts_with = "crates",
+        conflicts_with = "dev",
+        conflicts_with = "build",
+        conflicts_with = "target",
+        conflicts_with = "all-sections"
+    )]
+    unused: bool,
+
     /// Remove crate as development dependency.
     #[clap(long, short = 'D', conflicts_with = "build")]
     dev: bool,
@@ -22,6 +40,19 @@ pub struct RmArgs {
     #[clap(long, short = 'B', conflicts_with = "dev")]
     build: bool,
 
+    /// Remove crate as dependency from the given target platform.
+    #[clap(long, forbid_empty_values = true)]
+    target: Option<String>,
+
+    /// Remove crate from every dependency section it appears in.
+    #[clap(
+        long,
+        conflicts_with = "dev",
+        conflicts_with = "build",
+        conflicts_with = "target"
+    )]
+    all_sections: bool,
+
     /// Path to the manifest to remove a dependency from.
     #[clap(
         long,
@@ -36,14 +67,30 @@ pub struct RmArgs {
         long = "package",
         short = 'p',
         value_name = "PKGID",
-        conflicts_with = "manifest-path"
+        conflicts_with = "manifest-path",
+        conflicts_with = "workspace"
     )]
     pkgid: Option<String>,
 
+    /// Remove the dependency from every package in the workspace.
+    ///
+    /// Packages without the dependency are skipped. It is also removed from
+    /// `[workspace.dependencies]` once no package inherits it anymore.
+    #[clap(long)]
+    workspace: bool,
+
+    /// Packages to leave untouched with `--workspace`.
+    #[clap(long, value_name = "PKGID", requires = "workspace")]
+    exclude: Vec<String>,
+
     /// Unstable (nightly-only) flags
     #[clap(short = 'Z', value_name = "FLAG", global = true, arg_enum)]
     unstable_features: Vec<UnstableOptions>,
 
+    /// Don't actually write the manifest
+    #[clap(long)]
+    dry_run: bool,
+
     /// Do not print any output in case of success.
     #[clap(long, short)]
     quiet: bool,
@@ -55,31 +102,150 @@ impl RmArgs {
     }
 
     /// Get depenency section
-    pub fn get_section(&self) -> &'static str {
-        if self.dev {
+    pub fn get_section(&self) -> Vec<String> {
+        let section = if self.dev {
             "dev-dependencies"
         } else if self.build {
             "build-dependencies"
         } else {
             "dependencies"
+        };
+        if let Some(ref target) = self.target {
+            vec!["target".to_owned(), target.clone(), section.to_owned()]
+        } else {
+            vec![section.to_owned()]
         }
     }
+
+    /// Get every section to remove `dep` from
+    fn get_sections(&self, manifest: &LocalManifest, dep: &str) -> Vec<Vec<String>> {
+        if self.all_sections {
+            let sections: Vec<_> = manifest
+                .get_sections()
+                .into_iter()
+                .filter(|(_, table)| table.get(dep).is_some_and(|item| !item.is_none()))
+                .map(|(section, _)| section)
+                .collect();
+            if !sections.is_empty() {
+                return sections;
+            }
+        }
+        vec![self.get_section()]
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ArgEnum)]
 enum UnstableOptions {}
 
-fn print_msg(name: &str, section: &str) -> CargoResult<()> {
+fn print_msg(name: &str, section: &[String]) -> CargoResult<()> {
     let colorchoice = colorize_stderr();
     let mut output = StandardStream::stderr(colorchoice);
     output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
     write!(output, "{:>12}", "Removing")?;
     output.reset()?;
-    writeln!(output, " {} from {}", name, section)?;
+    writeln!(output, " {} from {}", name, section_name(section))?;
+    Ok(())
+}
+
+fn print_cleanup(cleanup: &FeatureCleanup) -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+    match cleanup {
+        FeatureCleanup::Removed {
+            feature,
+            activation,
+        } => {
+            write!(output, "{:>12}", "Removing")?;
+            output.reset()?;
+            writeln!(output, " {} from features.{}", activation, feature)?;
+        }
+        FeatureCleanup::Rewritten {
+            feature,
+            activation,
+            replacement,
+        } => {
+            write!(output, "{:>12}", "Updating")?;
+            output.reset()?;
+            writeln!(
+                output,
+                " {} to {} in features.{}",
+                activation, replacement, feature
+            )?;
+        }
+        FeatureCleanup::FeatureRemoved {
+            feature,
+            references,
+        } => {
+            write!(output, "{:>12}", "Removing")?;
+            output.reset()?;
+            writeln!(
+                output,
+                " {} from features, nothing is left for it to activate",
+                feature
+            )?;
+            for reference in references {
+                output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+                write!(output, "{:>12}", "Removing")?;
+                output.reset()?;
+                writeln!(output, " {} from {}", feature, reference)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn section_name(section: &[String]) -> String {
+    if section.len() == 1 {
+        section[0].clone()
+    } else if section[0] == "workspace" {
+        format!("workspace {}", section[1])
+    } else {
+        format!("{} for target `{}`", &section[2], &section[1])
+    }
+}
+
+fn move_msg(name: &str, section: &[String], move_to: &[String]) -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+    write!(output, "{:>12}", "Warning:")?;
+    output.reset()?;
+    let used_by = if move_to
+        .last()
+        .is_some_and(|table| table == "build-dependencies")
+    {
+        "the build script"
+    } else {
+        "tests, examples or benchmarks"
+    };
+    writeln!(
+        output,
+        " {} is only used by {}, consider moving it from {} to {}",
+        name,
+        used_by,
+        section_name(section),
+        section_name(move_to)
+    )?;
+    Ok(())
+}
+
+fn dry_run_message() -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+    write!(output, "{:>12}", "Warning:")?;
+    output.reset()?;
+    writeln!(output, " aborting rm due to dry run")
+        .with_context(|| "Failed to write dry run message")?;
     Ok(())
 }
 
 fn exec(args: &RmArgs) -> CargoResult<()> {
+    if args.workspace {
+        return exec_workspace(args);
+    }
+
     let manifest_path = if let Some(ref pkgid) = args.pkgid {
         let pkg = manifest_from_pkgid(args.manifest_path.as_deref(), pkgid)?;
         Cow::Owned(Some(pkg.manifest_path.into_std_path_buf()))
@@ -87,30 +253,234 @@ fn exec(args: &RmArgs) -> CargoResult<()> {
         Cow::Borrowed(&args.manifest_path)
     };
     let mut manifest = LocalManifest::find(manifest_path.as_deref())?;
-    let deps = &args.crates;
+    let deps = if args.unused {
+        let package = find_package(&manifest)?;
+        let metadata = resolve_workspace(Some(&manifest.path))?;
+        let unused = find_unused_dependencies(&manifest, &package, &metadata)?;
+        remove_unused(&mut manifest, &unused, args.quiet)?
+    } else {
+        remove_crates(args, &mut manifest)?;
+        args.crates.clone()
+    };
 
-    deps.iter()
-        .map(|dep| {
-            if !args.quiet {
-                print_msg(dep, args.get_section())?;
-            }
-            let result = manifest
-                .remove_from_table(args.get_section(), dep)
-                .map_err(Into::into);
+    // Now that we have removed the crates, if that was the last reference to a crate, then we
+    // need to drop any explicitly activated features on that crate.
+    gc_deps(&mut manifest, &deps, args.quiet)?;
 
-            // Now that we have removed the crate, if that was the last reference to that crate,
-            // then we need to drop any explicitly activated features on that crate.
-            manifest.gc_dep(dep);
+    if args.dry_run {
+        dry_run_message()?;
+    } else {
+        let mut transaction = ManifestTransaction::new();
+        transaction.stage(&manifest)?;
+        transaction.commit()?;
+    }
 
-            result
+    Ok(())
+}
+
+fn remove_crates(args: &RmArgs, manifest: &mut LocalManifest) -> CargoResult<()> {
+    args.crates
+        .iter()
+        .map(|dep| {
+            for section in args.get_sections(manifest, dep) {
+                if !args.quiet {
+                    print_msg(dep, &section)?;
+                }
+                manifest.remove_from_table_path(&section, dep)?;
+            }
+            Ok(())
         })
         .collect::<CargoResult<Vec<_>>>()
         .map_err(|err| {
             eprintln!("Could not edit `Cargo.toml`.\n\nERROR: {}", err);
             err
         })?;
+    Ok(())
+}
+
+fn exec_workspace(args: &RmArgs) -> CargoResult<()> {
+    let mut transaction = ManifestTransaction::new();
+    let mut packages = workspace_members(args.manifest_path.as_deref())?;
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    let metadata = if args.unused {
+        Some(resolve_workspace(args.manifest_path.as_deref())?)
+    } else {
+        None
+    };
+
+    let mut found: Vec<String> = Vec::new();
+    for package in &packages {
+        if args.exclude.contains(&package.name) {
+            continue;
+        }
+        let mut manifest = transaction.open(package.manifest_path.as_std_path())?;
+        if let Some(metadata) = &metadata {
+            let unused = find_unused_dependencies(&manifest, package, metadata)?;
+            if unused.is_empty() {
+                continue;
+            }
+            if !args.quiet {
+                println!("{}:", package.name);
+            }
+            let removed = remove_unused(&mut manifest, &unused, args.quiet)?;
+            gc_deps(&mut manifest, &removed, args.quiet)?;
+            found.extend(removed);
+            transaction.stage(&manifest)?;
+            continue;
+        }
+        let removals: Vec<_> = args
+            .crates
+            .iter()
+            .flat_map(|dep| {
+                args.get_sections(&manifest, dep)
+                    .into_iter()
+                    .filter(|section| has_dependency(&manifest, section, dep))
+                    .map(move |section| (dep, section))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        if removals.is_empty() {
+            continue;
+        }
+
+        if !args.quiet {
+            println!("{}:", package.name);
+        }
+        for (dep, section) in removals {
+            if !args.quiet {
+                print_msg(dep, &section)?;
+            }
+            manifest.remove_from_table_path(&section, dep)?;
+            found.push(dep.clone());
+        }
+        gc_deps(&mut manifest, &args.crates, args.quiet)?;
+        transaction.stage(&manifest)?;
+    }
+
+    let root = match LocalManifest::find(args.manifest_path.as_deref())?.workspace_root()? {
+        Some(root) => Some(transaction.open(&root)?),
+        None => None,
+    };
+    if let Some(mut root) = root {
+        let workspace_section = vec!["workspace".to_owned(), "dependencies".to_owned()];
+        let mut removed_any = false;
+        // With `--unused`, only drop what no package inherits anymore
+        let deps = if args.unused { &found } else { &args.crates };
+        let mut removed = Vec::new();
+        for dep in deps {
+            if !has_dependency(&root, &workspace_section, dep)
+                || is_inherited(&packages, &transaction, dep)?
+            {
+                continue;
+            }
+            if !args.quiet {
+                if !removed_any {
+                    println!("workspace:");
+                }
+                print_msg(dep, &workspace_section)?;
+            }
+            root.remove_from_table_path(&workspace_section, dep)?;
+            removed_any = true;
+            removed.push(dep.clone());
+        }
+        found.extend(removed);
+        if removed_any {
+            transaction.stage_workspace(&root)?;
+        }
+    }
+
+    for dep in &args.crates {
+        if !found.contains(dep) {
+            anyhow::bail!(
+                "The dependency `{}` could not be found in any package of the workspace.",
+                dep
+            );
+        }
+    }
+
+    if args.dry_run {
+        dry_run_message()?;
+    } else {
+        transaction.commit()?;
+    }
 
-    manifest.write()?;
+    Ok(())
+}
+
+/// Remove the unused dependencies, returning their names, and point out those to move
+fn remove_unused(
+    manifest: &mut LocalManifest,
+    unused: &[UnusedDependency],
+    quiet: bool,
+) -> CargoResult<Vec<String>> {
+    let mut removed = Vec::new();
+    for dep in unused {
+        if let Some(move_to) = &dep.move_to {
+            if !quiet {
+                move_msg(&dep.key, &dep.section, move_to)?;
+            }
+            continue;
+        }
+        if !quiet {
+            print_msg(&dep.key, &dep.section)?;
+        }
+        manifest.remove_from_table_path(&dep.section, &dep.key)?;
+        removed.push(dep.key.clone());
+    }
+    Ok(removed)
+}
 
+/// Clean up the features referring to `deps`, reporting the changes
+fn gc_deps(manifest: &mut LocalManifest, deps: &[String], quiet: bool) -> CargoResult<()> {
+    for dep in deps {
+        let cleanups = manifest.gc_dep(dep)?;
+        if !quiet {
+            for cleanup in &cleanups {
+                print_cleanup(cleanup)?;
+            }
+        }
+    }
     Ok(())
 }
+
+/// Find the package `manifest` belongs to, for its targets
+fn find_package(manifest: &LocalManifest) -> CargoResult<cargo_metadata::Package> {
+    let manifest_path = dunce::canonicalize(&manifest.path)?;
+    workspace_members(Some(&manifest.path))?
+        .into_iter()
+        .find(|package| package.manifest_path == manifest_path)
+        .with_context(|| {
+            "Found virtual manifest, but this command requires running against an \
+             actual package in this workspace. Try adding `--workspace`."
+        })
+}
+
+fn has_dependency(manifest: &LocalManifest, section: &[String], dep: &str) -> bool {
+    manifest
+        .get_table(section)
+        .ok()
+        .and_then(|table| table.get(dep))
+        .is_some_and(|item| !item.is_none())
+}
+
+/// Whether any package, including excluded ones, still inherits `dep` from the workspace
+fn is_inherited(
+    packages: &[cargo_metadata::Package],
+    transaction: &ManifestTransaction,
+    dep: &str,
+) -> CargoResult<bool> {
+    for package in packages {
+        let manifest = transaction.open(package.manifest_path.as_std_path())?;
+        let inherited = manifest.get_sections().into_iter().any(|(_, table)| {
+            table
+                .get(dep)
+                .and_then(|item| item.get("workspace"))
+                .and_then(|item| item.as_bool())
+                .unwrap_or(false)
+        });
+        if inherited {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
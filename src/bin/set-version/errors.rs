@@ -1,7 +1,6 @@
 use std::fmt::Display;
 
 pub use cargo_edit_9::CargoResult;
-pub use cargo_edit_9::CliResult;
 pub use cargo_edit_9::Context;
 pub use cargo_edit_9::Error;
 
@@ -9,3 +8,20 @@ pub use cargo_edit_9::Error;
 pub(crate) fn version_downgrade_err(current: impl Display, requested: impl Display) -> Error {
     anyhow::format_err!("Cannot downgrade from {} to {}", current, requested)
 }
+
+/// Package inherits its version but no workspace root declares it
+pub(crate) fn no_workspace_version_err(name: impl Display) -> Error {
+    anyhow::format_err!(
+        "`{}` inherits its version from the workspace but no workspace root was found",
+        name
+    )
+}
+
+/// Bumping the workspace version would also bump an excluded package inheriting it
+pub(crate) fn excluded_workspace_version_err(name: impl Display, excluded: impl Display) -> Error {
+    anyhow::format_err!(
+        "Cannot bump the workspace version for `{}` because the excluded `{}` inherits it too",
+        name,
+        excluded
+    )
+}
@@ -1,10 +1,11 @@
+use std::collections::BTreeSet;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 
 use cargo_edit_9::{
     colorize_stderr, find, manifest_from_pkgid, upgrade_requirement, workspace_members,
-    LocalManifest,
+    LocalManifest, ManifestTransaction,
 };
 use clap::Args;
 use termcolor::{BufferWriter, Color, ColorSpec, WriteColor};
@@ -23,11 +24,7 @@ pub struct VersionArgs {
     target: Option<semver::Version>,
 
     /// Increment manifest version
-    #[clap(
-        long,
-        possible_values(crate::version::BumpLevel::variants()),
-        group = "ver"
-    )]
+    #[clap(long, possible_values(BumpLevel::variants()), group = "ver")]
     bump: Option<BumpLevel>,
 
     /// Specify the version metadata field (e.g. a wrapped libraries version)
@@ -129,70 +126,172 @@ fn exec(args: VersionArgs) -> CargoResult<()> {
     }
 
     let workspace_members = workspace_members(manifest_path.as_deref())?;
-
+    let workspace_root = LocalManifest::find(manifest_path.as_deref())?.workspace_root()?;
+
+    // Manifests are only written once every package was bumped successfully
+    let mut transaction = ManifestTransaction::new();
+    // Packages with `version.workspace = true` share a single version, bumped at most once
+    let mut inheriting = BTreeSet::new();
+    for member in workspace_members.iter() {
+        if transaction
+            .open(member.manifest_path.as_std_path())?
+            .is_package_version_inherited()
+        {
+            inheriting.insert(member.name.as_str());
+        }
+    }
+    let mut bumped_workspace = false;
     for package in manifests.0 {
         if exclude.contains(&package.name) {
             continue;
         }
         let current = &package.version;
         let next = target.bump(current, metadata.as_deref())?;
-        if let Some(next) = next {
-            {
-                let mut manifest = LocalManifest::try_new(Path::new(&package.manifest_path))?;
-                manifest.set_package_version(&next);
 
-                upgrade_message(package.name.as_str(), current, &next)?;
-                if !dry_run {
-                    manifest.write()?;
-                }
+        if inheriting.contains(package.name.as_str()) {
+            if bumped_workspace {
+                continue;
+            }
+            bumped_workspace = true;
+            let next = match next {
+                Some(next) => next,
+                None => continue,
+            };
+            if let Some(excluded) = exclude
+                .iter()
+                .find(|name| inheriting.contains(name.as_str()))
+            {
+                return Err(excluded_workspace_version_err(&package.name, excluded));
             }
 
-            let crate_root =
-                dunce::canonicalize(package.manifest_path.parent().expect("at least a parent"))?;
+            let root_path = workspace_root
+                .as_deref()
+                .ok_or_else(|| no_workspace_version_err(&package.name))?;
+            let mut root = transaction.open(root_path)?;
+            root.set_workspace_package_version(&next);
+            transaction.stage_workspace(&root)?;
+
             for member in workspace_members.iter() {
-                let mut dep_manifest = LocalManifest::try_new(member.manifest_path.as_std_path())?;
-                let mut changed = false;
-                let dep_crate_root = dep_manifest
-                    .path
-                    .parent()
-                    .expect("at least a parent")
-                    .to_owned();
-                for dep in dep_manifest
-                    .get_dependency_tables_mut()
-                    .flat_map(|t| t.iter_mut().filter_map(|(_, d)| d.as_table_like_mut()))
-                    .filter(|d| {
-                        if !d.contains_key("version") {
-                            return false;
-                        }
-                        match d.get("path").and_then(|i| i.as_str()).and_then(|relpath| {
-                            dunce::canonicalize(dep_crate_root.join(relpath)).ok()
-                        }) {
-                            Some(dep_path) => dep_path == crate_root.as_path(),
-                            None => false,
-                        }
-                    })
-                {
-                    let old_req = dep
-                        .get("version")
-                        .expect("filter ensures this")
-                        .as_str()
-                        .unwrap_or("*");
-                    if let Some(new_req) = upgrade_requirement(old_req, &next)? {
-                        upgrade_dependent_message(member.name.as_str(), old_req, &new_req)?;
-                        dep.insert("version", toml_edit::value(new_req));
-                        changed = true;
-                    }
-                }
-                if changed && !dry_run {
-                    dep_manifest.write()?;
+                if inheriting.contains(member.name.as_str()) {
+                    upgrade_message(member.name.as_str(), current, &next)?;
+                    update_dependents(
+                        member.manifest_path.as_std_path(),
+                        &next,
+                        &workspace_members,
+                        workspace_root.as_deref(),
+                        &mut transaction,
+                    )?;
                 }
             }
+        } else if let Some(next) = next {
+            let mut manifest = transaction.open(Path::new(&package.manifest_path))?;
+            manifest.set_package_version(&next);
+
+            upgrade_message(package.name.as_str(), current, &next)?;
+            transaction.stage(&manifest)?;
+
+            update_dependents(
+                Path::new(&package.manifest_path),
+                &next,
+                &workspace_members,
+                workspace_root.as_deref(),
+                &mut transaction,
+            )?;
+        }
+    }
+
+    if !dry_run {
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Rewrite the requirements of path dependencies on the package at `manifest_path`, both in the
+/// workspace members and in `[workspace.dependencies]`.
+fn update_dependents(
+    manifest_path: &Path,
+    next: &semver::Version,
+    workspace_members: &[cargo_metadata::Package],
+    workspace_root: Option<&Path>,
+    transaction: &mut ManifestTransaction,
+) -> CargoResult<()> {
+    let crate_root = dunce::canonicalize(manifest_path.parent().expect("at least a parent"))?;
+    for member in workspace_members.iter() {
+        let mut dep_manifest = transaction.open(member.manifest_path.as_std_path())?;
+        let dep_crate_root = dep_manifest
+            .path
+            .parent()
+            .expect("at least a parent")
+            .to_owned();
+        let changed = update_dependent_tables(
+            dep_manifest.get_dependency_tables_mut(),
+            &dep_crate_root,
+            &crate_root,
+            next,
+            member.name.as_str(),
+        )?;
+        if changed {
+            transaction.stage(&dep_manifest)?;
+        }
+    }
+
+    if let Some(workspace_root) = workspace_root {
+        let mut root = transaction.open(workspace_root)?;
+        let root_crate_root = root.path.parent().expect("at least a parent").to_owned();
+        let changed = update_dependent_tables(
+            root.get_workspace_dependency_table_mut().into_iter(),
+            &root_crate_root,
+            &crate_root,
+            next,
+            "workspace",
+        )?;
+        if changed {
+            transaction.stage_workspace(&root)?;
         }
     }
 
     Ok(())
 }
 
+fn update_dependent_tables<'t>(
+    tables: impl Iterator<Item = &'t mut dyn toml_edit::TableLike>,
+    dep_crate_root: &Path,
+    crate_root: &Path,
+    next: &semver::Version,
+    name: &str,
+) -> CargoResult<bool> {
+    let mut changed = false;
+    for dep in tables
+        .flat_map(|t| t.iter_mut().filter_map(|(_, d)| d.as_table_like_mut()))
+        .filter(|d| {
+            if !d.contains_key("version") {
+                return false;
+            }
+            match d
+                .get("path")
+                .and_then(|i| i.as_str())
+                .and_then(|relpath| dunce::canonicalize(dep_crate_root.join(relpath)).ok())
+            {
+                Some(dep_path) => dep_path == crate_root,
+                None => false,
+            }
+        })
+    {
+        let old_req = dep
+            .get("version")
+            .expect("filter ensures this")
+            .as_str()
+            .unwrap_or("*");
+        if let Some(new_req) = upgrade_requirement(old_req, next)? {
+            upgrade_dependent_message(name, old_req, &new_req)?;
+            dep.insert("version", toml_edit::value(new_req));
+            changed = true;
+        }
+    }
+    Ok(changed)
+}
+
 /// A collection of manifests.
 struct Manifests(Vec<cargo_metadata::Package>);
 
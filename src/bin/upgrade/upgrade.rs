@@ -3,8 +3,9 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use cargo_edit_9::{
-    colorize_stderr, find, get_latest_dependency, manifest_from_pkgid, registry_url,
-    update_registry_index, CargoResult, Context, CrateSpec, Dependency, LocalManifest,
+    colorize_stderr, find, is_offline, manifest_from_pkgid, registry_url, update_registry_index,
+    CargoResult, Context, CrateSpec, Dependency, LocalManifest, ManifestTransaction, RustVersion,
+    UpgradeCandidates, UpgradeScope, VersionResolver,
 };
 use clap::Args;
 use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
@@ -24,7 +25,16 @@ Dev, build, and all target dependencies will also be upgraded. Only dependencies
 are supported. Git/path dependencies will be ignored.
 
 All packages in the workspace will be upgraded if the `--workspace` flag is supplied. The \
-`--workspace` flag may be supplied in the presence of a virtual manifest.
+`--workspace` flag may be supplied in the presence of a virtual manifest. Dependencies declared in \
+`[workspace.dependencies]` are upgraded once for the whole workspace.
+
+With `--compatible`, requirements are only raised to the latest version they are compatible with, \
+e.g. `1.2` to `1.9`. With `--incompatible`, only the requirements the latest version is \
+incompatible with are upgraded. Either way, the newer versions left out are reported as held back.
+
+A table is printed for each package, listing the locked, latest compatible, and latest versions of \
+its dependencies, their new requirements, and notes on the kind of upgrade or why any were left \
+alone.
 
 If the '--to-lockfile' flag is supplied, all dependencies will be upgraded to the currently locked \
 version as recorded in the Cargo.lock file. This flag requires that the Cargo.lock file is \
@@ -75,14 +85,37 @@ pub struct UpgradeArgs {
     #[clap(long)]
     dry_run: bool,
 
-    /// Only update a dependency if the new version is semver incompatible.
+    /// Only upgrade to the latest version the current requirement is compatible with, e.g. `1.2`
+    /// to `1.9`
+    #[clap(
+        long,
+        conflicts_with = "incompatible",
+        conflicts_with = "skip-compatible",
+        conflicts_with = "to-lockfile"
+    )]
+    compatible: bool,
+
+    /// Only upgrade requirements the latest version is incompatible with, e.g. `1.2` to `2.0`
     #[clap(long, conflicts_with = "to-lockfile")]
+    incompatible: bool,
+
+    /// Only update a dependency if the new version is semver incompatible.
+    #[clap(
+        long,
+        help = "[deprecated in favor of `--incompatible`]",
+        conflicts_with = "to-lockfile"
+    )]
     skip_compatible: bool,
 
     /// Run without accessing the network
     #[clap(long)]
     offline: bool,
 
+    /// Upgrade to the latest versions even if they require a newer Rust than the packages'
+    /// `rust-version`
+    #[clap(long, conflicts_with = "to-lockfile")]
+    ignore_rust_version: bool,
+
     /// Upgrade all packages to the version in the lockfile.
     #[clap(long, conflicts_with = "dependency")]
     to_lockfile: bool,
@@ -115,6 +148,16 @@ impl UpgradeArgs {
         }
     }
 
+    fn scope(&self) -> UpgradeScope {
+        if self.compatible {
+            UpgradeScope::Compatible
+        } else if self.incompatible || self.skip_compatible {
+            UpgradeScope::Incompatible
+        } else {
+            UpgradeScope::All
+        }
+    }
+
     fn preserve_precision(&self) -> bool {
         self.unstable_features
             .contains(&UnstableOptions::PreservePrecision)
@@ -128,63 +171,152 @@ enum UnstableOptions {
 
 /// Main processing function. Allows us to return a `Result` so that `main` can print pretty error
 /// messages.
-fn exec(args: UpgradeArgs) -> CargoResult<()> {
+fn exec(mut args: UpgradeArgs) -> CargoResult<()> {
     if args.all {
         deprecated_message("The flag `--all` has been deprecated in favor of `--workspace`")?;
     }
+    if args.skip_compatible {
+        deprecated_message(
+            "The flag `--skip-compatible` has been deprecated in favor of `--incompatible`",
+        )?;
+    }
 
-    if !args.offline && !args.to_lockfile && std::env::var("CARGO_IS_TEST").is_err() {
-        let url = registry_url(&find(args.manifest_path.as_deref())?, None)?;
-        update_registry_index(&url, false)?;
+    args.offline = args.offline || is_offline(&find(args.manifest_path.as_deref())?)?;
+    if !args.offline && !args.to_lockfile {
+        let manifest_path = find(args.manifest_path.as_deref())?;
+        let url = registry_url(&manifest_path, None)?;
+        update_registry_index(&url, &manifest_path, false)?;
     }
 
     let manifests = args.resolve_targets()?;
-    let locked = if std::env::var("CARGO_IS_TEST").is_err() {
+    let workspace = if args.workspace() {
+        resolve_workspace_root(args.manifest_path.as_deref())?
+    } else {
+        None
+    };
+    // Without a lock file, there are no locked versions to report
+    let locked = if args.to_lockfile || has_lockfile(&manifests)? {
         load_lockfile(&manifests)?
     } else {
-        load_lockfile(&manifests).unwrap_or_default()
+        Vec::new()
     };
     let preserve_precision = args.preserve_precision();
+    let scope = args.scope();
 
-    let mut updated_registries = BTreeSet::new();
-    for (manifest, package) in manifests {
-        let existing_dependencies = get_dependencies(&manifest, &args.dependency, &args.exclude)?;
-
-        let upgraded_dependencies = if args.to_lockfile {
-            existing_dependencies.into_lockfile(&locked, preserve_precision)?
-        } else {
-            // Update indices for any alternative registries, unless
-            // we're offline.
-            if !args.offline && std::env::var("CARGO_IS_TEST").is_err() {
-                for registry_url in existing_dependencies
-                    .0
-                    .values()
-                    .filter_map(|UpgradeMetadata { registry, .. }| registry.as_ref())
-                {
-                    if updated_registries.insert(registry_url.to_owned()) {
-                        update_registry_index(registry_url, false)?;
-                    }
-                }
+    let resolver = if args.to_lockfile {
+        None
+    } else {
+        let manifest_path = find(args.manifest_path.as_deref())?;
+        let resolver = VersionResolver::new(&manifest_path, args.offline)?;
+        // Look every dependency of the workspace up at once, rather than member by member
+        let mut desired = Vec::new();
+        if let Some(manifest) = &workspace {
+            desired.push(get_dependencies(
+                manifest,
+                manifest.get_workspace_dependencies(),
+                &args.dependency,
+                &args.exclude,
+            )?);
+        }
+        for (manifest, _) in &manifests {
+            desired.push(get_dependencies(
+                manifest,
+                manifest.get_dependencies(),
+                &args.dependency,
+                &args.exclude,
+            )?);
+        }
+        let lookups: Vec<_> = desired
+            .iter()
+            .flat_map(|upgrades| upgrades.0.iter())
+            .filter(|(_, metadata)| metadata.skip.is_none_or(Skip::is_registry))
+            .map(|(dep, metadata)| (dep.name.as_str(), metadata.registry.as_ref()))
+            .collect();
+
+        // Update indices for any alternative registries, unless
+        // we're offline.
+        if !args.offline {
+            let registries: BTreeSet<_> = lookups
+                .iter()
+                .filter_map(|(_, registry)| *registry)
+                .collect();
+            for registry_url in registries {
+                update_registry_index(registry_url, &manifest_path, false)?;
             }
+        }
+        resolver.prefetch(lookups);
+        Some(resolver)
+    };
+    let resolve_upgrades = |existing_dependencies: DesiredUpgrades, rust_version| match &resolver {
+        Some(resolver) => existing_dependencies.into_latest(
+            args.allow_prerelease,
+            resolver,
+            &locked,
+            rust_version,
+            scope,
+            preserve_precision,
+        ),
+        None => existing_dependencies.into_lockfile(&locked, preserve_precision),
+    };
 
-            existing_dependencies.into_latest(
-                args.allow_prerelease,
-                &find(args.manifest_path.as_deref())?,
-                preserve_precision,
-            )?
-        };
+    // Manifests are only written once everything was upgraded successfully
+    let mut transaction = ManifestTransaction::new();
+    if let Some(mut manifest) = workspace {
+        let existing_dependencies = get_dependencies(
+            &manifest,
+            manifest.get_workspace_dependencies(),
+            &args.dependency,
+            &args.exclude,
+        )?;
+        // Shared by the members, which usually inherit the workspace's `rust-version`
+        let rust_version = match manifest.workspace_rust_version()? {
+            Some(rust_version) => Some(rust_version),
+            None => manifest.rust_version()?,
+        }
+        .filter(|_| !args.ignore_rust_version);
+        let upgraded_dependencies = resolve_upgrades(existing_dependencies, rust_version)?;
+
+        println!("workspace:");
+        for dep in upgraded_dependencies.to_dependencies() {
+            manifest.upgrade_workspace(&dep, args.dry_run, scope)?;
+        }
+        print_report(&upgraded_dependencies.report);
+        print_held_back(&upgraded_dependencies.held_back)?;
+        if !upgraded_dependencies.versions.is_empty() {
+            transaction.stage_workspace(&manifest)?;
+        }
+    }
 
-        upgrade(
+    for (manifest, package) in manifests {
+        // The workspace root might be a package too, whose manifest was edited above
+        let manifest = transaction.open(&manifest.path)?;
+        let existing_dependencies = get_dependencies(
+            &manifest,
+            manifest.get_dependencies(),
+            &args.dependency,
+            &args.exclude,
+        )?;
+        let rust_version = manifest
+            .rust_version()?
+            .filter(|_| !args.ignore_rust_version);
+        let upgraded_dependencies = resolve_upgrades(existing_dependencies, rust_version)?;
+
+        let manifest = upgrade(
             manifest,
             package,
             &upgraded_dependencies,
             args.dry_run,
-            args.skip_compatible,
+            scope,
         )?;
+        if !upgraded_dependencies.versions.is_empty() {
+            transaction.stage(&manifest)?;
+        }
     }
 
     if args.dry_run {
         dry_run_message()?;
+    } else {
+        transaction.commit()?;
     }
 
     Ok(())
@@ -194,6 +326,7 @@ fn exec(args: UpgradeArgs) -> CargoResult<()> {
 /// per-dependency desired versions, extract those here.
 fn get_dependencies(
     manifest: &LocalManifest,
+    dependencies: impl Iterator<Item = (Vec<String>, CargoResult<Dependency>)>,
     only_update: &[String],
     exclude: &[String],
 ) -> CargoResult<DesiredUpgrades> {
@@ -207,81 +340,100 @@ fn get_dependencies(
         .collect::<CargoResult<BTreeMap<_, _>>>()?;
 
     let mut upgrades = DesiredUpgrades::default();
-    for (dependency, old_version) in manifest
-        .get_dependencies()
+    for dependency in dependencies
         .map(|(_, result)| result)
         .collect::<CargoResult<Vec<_>>>()?
         .into_iter()
-        .filter(|dependency| dependency.path().is_none())
-        .filter_map(|dependency| {
-            dependency
-                .version()
-                .map(ToOwned::to_owned)
-                .map(|version| (dependency, version))
-        })
-        .filter(|(dependency, _)| !exclude.contains(&dependency.name))
-        // Exclude renamed dependencies as well
-        .filter(|(dependency, _)| {
-            dependency
-                .rename()
-                .map_or(true, |rename| !exclude.iter().any(|s| s == rename))
+        // Dependencies without a source are left to Cargo
+        .filter(|dependency| {
+            dependency.version().is_some()
+                || dependency.path().is_some()
+                || dependency.git().is_some()
+                || dependency.is_workspace()
         })
     {
-        let registry = dependency
-            .registry()
-            .map(|registry| registry_url(&manifest.path, Some(registry)))
-            .transpose()?;
-        let is_prerelease = dependency
-            .version()
-            .map_or(false, |version| version.contains('-'));
-        if selected_dependencies.is_empty() {
-            upgrades.0.insert(
-                dependency,
-                UpgradeMetadata {
-                    registry,
-                    version: None,
-                    old_version,
-                    is_prerelease,
-                },
-            );
+        // User has asked for specific dependencies. Only report on those.
+        let version = match selected_dependencies.get(&dependency.name) {
+            Some(version) => version.clone(),
+            None if selected_dependencies.is_empty() => None,
+            None => continue,
+        };
+        let old_version = dependency.version().map(ToOwned::to_owned);
+        let is_excluded = exclude.contains(&dependency.name)
+            // Exclude renamed dependencies as well
+            || dependency
+                .rename()
+                .is_some_and(|rename| exclude.iter().any(|s| s == rename));
+        let skip = if dependency.path().is_some() {
+            Some(Skip::Path)
+        } else if dependency.git().is_some() {
+            Some(Skip::Git)
+        } else if dependency.is_workspace() {
+            Some(Skip::Workspace)
+        } else if is_excluded {
+            Some(Skip::Excluded)
         } else {
-            // User has asked for specific dependencies. Check if this dependency
-            // was specified, populating the registry from the lockfile metadata.
-            if let Some(version) = selected_dependencies.get(&dependency.name) {
-                upgrades.0.insert(
-                    dependency,
-                    UpgradeMetadata {
-                        registry,
-                        version: version.clone(),
-                        old_version,
-                        is_prerelease,
-                    },
-                );
-            }
-        }
+            None
+        };
+
+        let registry = if skip.is_some_and(|skip| !skip.is_registry()) {
+            None
+        } else if let Some(registry_index) = dependency.registry_index() {
+            Some(
+                Url::parse(registry_index)
+                    .with_context(|| format!("Invalid registry index URL `{}`", registry_index))?,
+            )
+        } else {
+            dependency
+                .registry()
+                .map(|registry| registry_url(&manifest.path, Some(registry)))
+                .transpose()?
+        };
+        let is_prerelease = old_version
+            .as_deref()
+            .is_some_and(|version| version.contains('-'));
+        upgrades.0.insert(
+            dependency,
+            UpgradeMetadata {
+                registry,
+                version,
+                old_version,
+                is_prerelease,
+                skip,
+            },
+        );
     }
     Ok(upgrades)
 }
 
-/// Upgrade the manifests on disk following the previously-determined upgrade schema.
+/// Upgrade the manifest following the previously-determined upgrade schema.
 fn upgrade(
     mut manifest: LocalManifest,
     package: cargo_metadata::Package,
     upgraded_deps: &ActualUpgrades,
     dry_run: bool,
-    skip_compatible: bool,
-) -> CargoResult<()> {
+    scope: UpgradeScope,
+) -> CargoResult<LocalManifest> {
     println!("{}:", package.name);
 
-    for (dep, version) in &upgraded_deps.0 {
-        let mut new_dep = Dependency::new(&dep.name).set_version(version);
-        if let Some(rename) = dep.rename() {
-            new_dep = new_dep.set_rename(rename);
-        }
-        manifest.upgrade(&new_dep, dry_run, skip_compatible)?;
+    for dep in upgraded_deps.to_dependencies() {
+        manifest.upgrade(&dep, dry_run, scope)?;
     }
+    print_report(&upgraded_deps.report);
+    print_held_back(&upgraded_deps.held_back)?;
 
-    Ok(())
+    Ok(manifest)
+}
+
+/// Whether the workspace of the targets has a lock file
+fn has_lockfile(targets: &[(LocalManifest, cargo_metadata::Package)]) -> CargoResult<bool> {
+    let (manifest, _package) = targets
+        .first()
+        .ok_or_else(|| anyhow::format_err!("Invalid cargo config"))?;
+    let root = manifest
+        .workspace_root()?
+        .unwrap_or_else(|| manifest.path.clone());
+    Ok(root.with_file_name("Cargo.lock").is_file())
 }
 
 fn load_lockfile(
@@ -291,7 +443,7 @@ fn load_lockfile(
     // files, there is only a single lockfile, so it suffices to get
     // metadata for any one of Cargo.toml files.
     let (manifest, _package) = targets
-        .get(0)
+        .first()
         .ok_or_else(|| anyhow::format_err!("Invalid cargo config"))?;
     let mut cmd = cargo_metadata::MetadataCommand::new();
     cmd.manifest_path(manifest.path.clone());
@@ -317,8 +469,39 @@ struct UpgradeMetadata {
     // `Some` if the user has specified an explicit
     // version to upgrade to.
     version: Option<String>,
-    old_version: String,
+    old_version: Option<String>,
     is_prerelease: bool,
+    // `Some` if the dependency is left alone, and why.
+    skip: Option<Skip>,
+}
+
+/// Why a dependency is reported without being upgraded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Skip {
+    /// The dependency is a local crate
+    Path,
+    /// The dependency is taken from a git repository
+    Git,
+    /// The requirement is inherited from `[workspace.dependencies]`
+    Workspace,
+    /// The user excluded the dependency
+    Excluded,
+}
+
+impl Skip {
+    /// Whether the dependency still comes from a registry, which can be queried for the report
+    fn is_registry(self) -> bool {
+        matches!(self, Skip::Excluded)
+    }
+
+    fn note(self) -> &'static str {
+        match self {
+            Skip::Path => "path",
+            Skip::Git => "git",
+            Skip::Workspace => "workspace",
+            Skip::Excluded => "excluded",
+        }
+    }
 }
 
 /// The set of dependencies to be upgraded, alongside the registries returned from cargo metadata, and
@@ -328,49 +511,84 @@ struct DesiredUpgrades(BTreeMap<Dependency, UpgradeMetadata>);
 
 impl DesiredUpgrades {
     /// Transform the dependencies into their upgraded forms. If a version is specified, all
-    /// dependencies will get that version.
+    /// dependencies will get that version. Otherwise, they get the latest version in `scope`
+    /// supporting `rust_version`, when there is one, and the newer versions out of `scope` are
+    /// held back.
+    ///
+    /// Dependencies left alone are still looked up for the report, ignoring failures.
     fn into_latest(
         self,
         allow_prerelease: bool,
-        manifest_path: &Path,
+        resolver: &VersionResolver,
+        locked: &[cargo_metadata::Package],
+        rust_version: Option<RustVersion>,
+        scope: UpgradeScope,
         preserve_precision: bool,
     ) -> CargoResult<ActualUpgrades> {
         let mut upgrades = ActualUpgrades::default();
-        for (
-            dep,
-            UpgradeMetadata {
-                registry,
-                version,
-                old_version,
-                is_prerelease,
-            },
-        ) in self.0.into_iter()
-        {
-            if let Some(v) = version {
-                upgrades.0.insert(dep, v);
-                continue;
+        for (dep, metadata) in self.0.into_iter() {
+            let upgrading = metadata.skip.is_none() && metadata.version.is_none();
+            let req = match metadata
+                .old_version
+                .as_deref()
+                .map(semver::VersionReq::parse)
+            {
+                Some(Ok(req)) => Some(req),
+                Some(Err(err)) if upgrading => return Err(err.into()),
+                _ => None,
+            };
+            let mut row = ReportRow::new(&dep, &metadata, req.as_ref(), locked);
+
+            let candidates = match &req {
+                Some(req) if metadata.skip.is_none_or(Skip::is_registry) => {
+                    let candidates = resolver.upgrade_candidates(
+                        &dep.name,
+                        req,
+                        allow_prerelease || metadata.is_prerelease,
+                        metadata.registry.as_ref(),
+                        rust_version,
+                    );
+                    if upgrading {
+                        Some(candidates.with_context(|| "Failed to get new version")?)
+                    } else {
+                        candidates.ok()
+                    }
+                }
+                _ => None,
+            };
+            if let Some(locked) = &row.locked {
+                row.yanked = metadata.skip.is_none_or(Skip::is_registry)
+                    && resolver
+                        .is_yanked(&dep.name, locked, metadata.registry.as_ref())
+                        .unwrap_or(false);
             }
 
-            let allow_prerelease = allow_prerelease || is_prerelease;
-
-            let latest = get_latest_dependency(
-                &dep.name,
-                allow_prerelease,
-                manifest_path,
-                registry.as_ref(),
-            )
-            .with_context(|| "Failed to get new version")?;
-            let latest_version = latest.version().expect("Invalid dependency type");
-            if preserve_precision {
-                let latest_version: semver::Version = latest_version.parse()?;
-                if let Some(version) =
-                    cargo_edit_9::upgrade_requirement(&old_version, &latest_version)?
-                {
-                    upgrades.0.insert(dep, version);
+            let new_version = match (&metadata.version, &req, &candidates) {
+                (Some(version), _, _) if metadata.skip.is_none() => Some(version.clone()),
+                (None, Some(req), Some(candidates)) if upgrading => {
+                    if let Some((version, kind)) = held_back(req, candidates, scope) {
+                        upgrades.held_back.insert((dep.name.clone(), version, kind));
+                    }
+                    match select_version(req, candidates, scope) {
+                        Some(new_version) => new_requirement(
+                            metadata.old_version.as_deref().unwrap_or_default(),
+                            &new_version,
+                            preserve_precision,
+                        )?,
+                        None => None,
+                    }
                 }
-            } else {
-                upgrades.0.insert(dep, latest_version.to_owned());
+                _ => None,
+            };
+            if let Some(candidates) = candidates {
+                row.compatible = candidates.compatible;
+                row.latest = Some(candidates.latest);
             }
+            row.set_new_req(new_version.as_deref(), req.as_ref());
+            if let Some(new_version) = new_version {
+                upgrades.versions.insert(dep, new_version);
+            }
+            upgrades.report.push(row);
         }
         Ok(upgrades)
     }
@@ -381,48 +599,220 @@ impl DesiredUpgrades {
         preserve_precision: bool,
     ) -> CargoResult<ActualUpgrades> {
         let mut upgrades = ActualUpgrades::default();
-        for (
-            dep,
-            UpgradeMetadata {
-                registry: _,
-                version,
-                old_version,
-                is_prerelease: _,
-            },
-        ) in self.0.into_iter()
-        {
-            if let Some(v) = version {
-                upgrades.0.insert(dep, v);
-                continue;
-            }
-
-            for p in locked {
-                // The requested dependency may be present in the lock file with different versions,
-                // but only one will be semver-compatible with the requested version.
-                let req = semver::VersionReq::parse(&old_version)?;
-                if dep.name == p.name && req.matches(&p.version) {
-                    let locked_version = &p.version;
-                    if preserve_precision {
-                        if let Some(version) =
-                            cargo_edit_9::upgrade_requirement(&old_version, locked_version)?
-                        {
-                            upgrades.0.insert(dep, version);
-                        }
-                    } else {
-                        upgrades.0.insert(dep, locked_version.to_string());
-                    }
-                    break;
-                }
+        for (dep, metadata) in self.0.into_iter() {
+            let upgrading = metadata.skip.is_none() && metadata.version.is_none();
+            let req = match metadata
+                .old_version
+                .as_deref()
+                .map(semver::VersionReq::parse)
+            {
+                Some(Ok(req)) => Some(req),
+                Some(Err(err)) if upgrading => return Err(err.into()),
+                _ => None,
+            };
+            let mut row = ReportRow::new(&dep, &metadata, req.as_ref(), locked);
+
+            let new_version = match (&metadata.version, &row.locked) {
+                (Some(version), _) if metadata.skip.is_none() => Some(version.clone()),
+                (None, Some(locked_version)) if upgrading => new_requirement(
+                    metadata.old_version.as_deref().unwrap_or_default(),
+                    locked_version,
+                    preserve_precision,
+                )?,
+                _ => None,
+            };
+            row.set_new_req(new_version.as_deref(), req.as_ref());
+            if let Some(new_version) = new_version {
+                upgrades.versions.insert(dep, new_version);
             }
+            upgrades.report.push(row);
         }
         Ok(upgrades)
     }
 }
 
+/// The version `req` is upgraded to within `scope`, if any
+fn select_version(
+    req: &semver::VersionReq,
+    candidates: &UpgradeCandidates,
+    scope: UpgradeScope,
+) -> Option<semver::Version> {
+    let floor = requirement_floor(req);
+    let is_newer = |version: &semver::Version| floor.as_ref().is_none_or(|f| version > f);
+    match scope {
+        // Never downgrade, which honoring `rust-version` could otherwise do
+        UpgradeScope::All => Some(candidates.latest.clone())
+            .filter(|latest| floor.as_ref().is_none_or(|f| latest >= f)),
+        UpgradeScope::Compatible => candidates.compatible.clone().filter(is_newer),
+        UpgradeScope::Incompatible if req.matches(&candidates.latest) => None,
+        UpgradeScope::Incompatible => Some(candidates.latest.clone()).filter(is_newer),
+    }
+}
+
+/// The newer version out of `scope` that `req` isn't upgraded to, if any, and its kind
+fn held_back(
+    req: &semver::VersionReq,
+    candidates: &UpgradeCandidates,
+    scope: UpgradeScope,
+) -> Option<(semver::Version, &'static str)> {
+    let floor = requirement_floor(req);
+    let is_newer = |version: &semver::Version| floor.as_ref().is_none_or(|f| version > f);
+    let latest_is_compatible = req.matches(&candidates.latest);
+    match scope {
+        UpgradeScope::All => None,
+        UpgradeScope::Compatible => Some((candidates.latest.clone(), "incompatible"))
+            .filter(|(latest, _)| !latest_is_compatible && is_newer(latest)),
+        UpgradeScope::Incompatible => candidates
+            .compatible
+            .clone()
+            .filter(|compatible| latest_is_compatible && is_newer(compatible))
+            .map(|compatible| (compatible, "compatible")),
+    }
+}
+
+/// The requirement replacing `old_version` to upgrade to `new_version`, if it changes
+fn new_requirement(
+    old_version: &str,
+    new_version: &semver::Version,
+    preserve_precision: bool,
+) -> CargoResult<Option<String>> {
+    if preserve_precision {
+        cargo_edit_9::upgrade_requirement(old_version, new_version)
+    } else {
+        Ok(Some(new_version.to_string()))
+    }
+}
+
+/// Whether `req` only matches a single version, like `=1.2.3`
+fn is_pinned(req: &str) -> bool {
+    semver::VersionReq::parse(req).is_ok_and(|req| {
+        matches!(req.comparators.as_slice(), [comparator] if comparator.op == semver::Op::Exact)
+    })
+}
+
 /// The complete specification of the upgrades that will be performed. Map of the dependency names
-/// to the new versions.
+/// to the new versions, along with the newer versions held back and why, and a report on every
+/// dependency considered.
 #[derive(Default, Clone, Debug)]
-struct ActualUpgrades(BTreeMap<Dependency, String>);
+struct ActualUpgrades {
+    versions: BTreeMap<Dependency, String>,
+    held_back: BTreeSet<(String, semver::Version, &'static str)>,
+    report: Vec<ReportRow>,
+}
+
+impl ActualUpgrades {
+    /// The dependencies to write back to the manifest
+    fn to_dependencies(&self) -> Vec<Dependency> {
+        self.versions
+            .iter()
+            .map(|(dep, version)| {
+                let mut new_dep = Dependency::new(&dep.name).set_version(version);
+                if let Some(rename) = dep.rename() {
+                    new_dep = new_dep.set_rename(rename);
+                }
+                new_dep
+            })
+            .collect()
+    }
+}
+
+/// What is known about a dependency, and what happens to it
+#[derive(Clone, Debug)]
+struct ReportRow {
+    name: String,
+    old_req: Option<String>,
+    /// The version recorded in the lock file
+    locked: Option<semver::Version>,
+    compatible: Option<semver::Version>,
+    latest: Option<semver::Version>,
+    /// `Some` if the requirement changes
+    new_req: Option<String>,
+    /// Whether the new requirement is a compatible or incompatible upgrade
+    bump: Option<&'static str>,
+    skip: Option<Skip>,
+    /// Whether the old requirement only matches a single version
+    pinned: bool,
+    prerelease: bool,
+    yanked: bool,
+}
+
+impl ReportRow {
+    fn new(
+        dep: &Dependency,
+        metadata: &UpgradeMetadata,
+        req: Option<&semver::VersionReq>,
+        locked: &[cargo_metadata::Package],
+    ) -> Self {
+        ReportRow {
+            name: dep.name.clone(),
+            old_req: metadata.old_version.clone(),
+            locked: req
+                .and_then(|req| locked_version(locked, &dep.name, req))
+                .cloned(),
+            compatible: None,
+            latest: None,
+            new_req: None,
+            bump: None,
+            skip: metadata.skip,
+            pinned: metadata.old_version.as_deref().is_some_and(is_pinned),
+            prerelease: metadata.is_prerelease,
+            yanked: false,
+        }
+    }
+
+    fn set_new_req(&mut self, new_req: Option<&str>, old_req: Option<&semver::VersionReq>) {
+        self.new_req = new_req
+            .filter(|new_req| self.old_req.as_deref() != Some(*new_req))
+            .map(ToOwned::to_owned);
+        self.bump = self
+            .new_req
+            .as_deref()
+            .and_then(|new_req| semver::VersionReq::parse(new_req).ok())
+            .and_then(|new_req| requirement_floor(&new_req))
+            .zip(old_req)
+            .map(|(floor, old_req)| {
+                if old_req.matches(&floor) {
+                    "compatible"
+                } else {
+                    "incompatible"
+                }
+            });
+        self.prerelease |= self
+            .new_req
+            .as_deref()
+            .is_some_and(|new_req| new_req.contains('-'));
+    }
+
+    fn note(&self) -> String {
+        let mut notes = Vec::new();
+        notes.extend(self.bump);
+        notes.extend(self.skip.map(Skip::note));
+        if self.pinned {
+            notes.push("pinned");
+        }
+        if self.prerelease {
+            notes.push("prerelease");
+        }
+        if self.yanked {
+            notes.push("yanked");
+        }
+        notes.join(", ")
+    }
+}
+
+/// The version of `name` in the lock file that `req` matches
+fn locked_version<'l>(
+    locked: &'l [cargo_metadata::Package],
+    name: &str,
+    req: &semver::VersionReq,
+) -> Option<&'l semver::Version> {
+    // The requested dependency may be present in the lock file with different versions, but only
+    // one will be semver-compatible with the requested version.
+    locked
+        .iter()
+        .find(|p| p.name == name && req.matches(&p.version))
+        .map(|p| &p.version)
+}
 
 /// Get all manifests in the workspace.
 fn resolve_all(
@@ -448,6 +838,17 @@ fn resolve_all(
         .collect::<CargoResult<Vec<_>>>()
 }
 
+/// Get the workspace root manifest, if it declares `[workspace.dependencies]`.
+fn resolve_workspace_root(manifest_path: Option<&Path>) -> CargoResult<Option<LocalManifest>> {
+    let manifest = LocalManifest::find(manifest_path)?;
+    let root = match manifest.workspace_root()? {
+        Some(root) if root == manifest.path => manifest,
+        Some(root) => LocalManifest::try_new(&root)?,
+        None => return Ok(None),
+    };
+    Ok((!root.get_workspace_sections().is_empty()).then_some(root))
+}
+
 fn resolve_pkgid(
     manifest_path: Option<&Path>,
     pkgid: &str,
@@ -486,6 +887,93 @@ fn resolve_local_one(
     Ok(vec![(manifest, package.to_owned())])
 }
 
+/// The oldest version `req` matches, when it sets a lower bound
+fn requirement_floor(req: &semver::VersionReq) -> Option<semver::Version> {
+    let comparator = req.comparators.iter().find(|comparator| {
+        matches!(
+            comparator.op,
+            semver::Op::Exact
+                | semver::Op::Caret
+                | semver::Op::Tilde
+                | semver::Op::Wildcard
+                | semver::Op::GreaterEq
+        )
+    })?;
+    Some(semver::Version {
+        major: comparator.major,
+        minor: comparator.minor.unwrap_or(0),
+        patch: comparator.patch.unwrap_or(0),
+        pre: comparator.pre.clone(),
+        build: semver::BuildMetadata::EMPTY,
+    })
+}
+
+/// Print `rows` as a table, with a column per field
+fn print_report(rows: &[ReportRow]) {
+    if rows.is_empty() {
+        return;
+    }
+    let header = [
+        "name",
+        "old req",
+        "locked",
+        "compatible",
+        "latest",
+        "new req",
+        "note",
+    ];
+    let cell = |value: Option<String>| value.unwrap_or_else(|| "-".to_owned());
+    let mut table = vec![
+        header.map(ToOwned::to_owned),
+        header.map(|title| "=".repeat(title.len())),
+    ];
+    table.extend(rows.iter().map(|row| {
+        [
+            row.name.clone(),
+            cell(row.old_req.clone()),
+            cell(row.locked.as_ref().map(ToString::to_string)),
+            cell(row.compatible.as_ref().map(ToString::to_string)),
+            cell(row.latest.as_ref().map(ToString::to_string)),
+            cell(row.new_req.clone()),
+            row.note(),
+        ]
+    }));
+
+    let mut widths = [0; 7];
+    for line in &table {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for line in &table {
+        let line = line
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Report the newer versions that weren't upgraded to
+fn print_held_back(held_back: &BTreeSet<(String, semver::Version, &str)>) -> CargoResult<()> {
+    let colorchoice = colorize_stderr();
+    let mut output = StandardStream::stderr(colorchoice);
+    for (name, version, kind) in held_back {
+        output
+            .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))
+            .with_context(|| "Failed to set output colour")?;
+        write!(output, " Holding back ").with_context(|| "Failed to write held back version")?;
+        output
+            .set_color(&ColorSpec::new())
+            .with_context(|| "Failed to clear output colour")?;
+        writeln!(output, "{} v{} ({})", name, version, kind)
+            .with_context(|| "Failed to write held back version")?;
+    }
+    Ok(())
+}
+
 fn deprecated_message(message: &str) -> CargoResult<()> {
     let colorchoice = colorize_stderr();
     let mut output = StandardStream::stderr(colorchoice);
@@ -94,6 +94,7 @@ impl CrateSpec {
                 Dependency::new(crate_name)
                     .set_path(path)
                     .set_available_features(available_features)
+                    .set_available_dependencies(manifest.available_dependencies())
             }
         };
 
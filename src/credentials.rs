@@ -0,0 +1,386 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use url::Url;
+
+use super::errors::*;
+use super::registry::{load_config, Config, CredentialProvider, CRATES_IO_REGISTRY};
+
+/// A registry token
+///
+/// It is never printed, even in debug output.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct Token(String);
+
+impl Token {
+    /// The value to send in the `Authorization` header
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Token(<redacted>)")
+    }
+}
+
+/// How to get the token of a registry, see
+/// <https://doc.rust-lang.org/cargo/reference/registry-authentication.html>
+#[derive(Debug)]
+pub(crate) struct Credentials {
+    /// Name of the registry
+    name: String,
+    /// URL of the index, as Cargo reports it to credential providers
+    index_url: String,
+    /// `token` from the config or credentials files
+    configured_token: Option<String>,
+    /// Providers to try, the first taking precedence
+    providers: Vec<Vec<String>>,
+}
+
+/// The credentials of the registry whose index is at `registry_url`, if it is a known registry
+pub(crate) fn registry_credentials(
+    manifest_path: &Path,
+    registry_url: &Url,
+) -> CargoResult<Option<Credentials>> {
+    let config = load_config(manifest_path)?;
+    Ok(credentials(&config, registry_url))
+}
+
+fn credentials(config: &Config, registry_url: &Url) -> Option<Credentials> {
+    let name = config.registry_name(registry_url)?;
+    let providers = match config.credential_provider(name) {
+        Some(provider) => vec![resolve_alias(config, provider)],
+        None => match &config.registry.global_credential_providers {
+            // Later entries take precedence
+            Some(providers) => providers
+                .iter()
+                .rev()
+                .map(|provider| resolve_alias(config, provider))
+                .collect(),
+            None => vec![vec!["cargo:token".to_owned()]],
+        },
+    };
+    Some(Credentials {
+        name: name.to_owned(),
+        index_url: registry_url.to_string(),
+        configured_token: config.token(name).map(str::to_owned),
+        providers,
+    })
+}
+
+fn resolve_alias(config: &Config, provider: &CredentialProvider) -> Vec<String> {
+    let mut args = provider.args();
+    if args.len() == 1 {
+        if let Some(alias) = config.credential_aliases.get(&args[0]) {
+            args = alias.args();
+        }
+    }
+    args
+}
+
+impl Credentials {
+    /// Name of the registry
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Ask the providers for a token, the first one having it winning
+    pub(crate) fn token(&self) -> CargoResult<Option<Token>> {
+        for provider in &self.providers {
+            if let Some(token) = self.provide(provider)? {
+                return Ok(Some(token));
+            }
+        }
+        Ok(None)
+    }
+
+    fn provide(&self, provider: &[String]) -> CargoResult<Option<Token>> {
+        let (program, args) = match provider.split_first() {
+            Some(split) => split,
+            None => anyhow::bail!(
+                "The credential provider of registry `{}` is empty",
+                self.name
+            ),
+        };
+        match program.as_str() {
+            "cargo:token" => Ok(self
+                .env_token()
+                .or_else(|| self.configured_token.clone())
+                .map(Token)),
+            "cargo:token-from-stdout" => token_from_stdout(&self.name, args).map(Some),
+            _ if program.starts_with("cargo:") => anyhow::bail!(
+                "The credential provider `{}` of registry `{}` is not supported",
+                program,
+                self.name
+            ),
+            _ => self.plugin(program, args),
+        }
+    }
+
+    /// `CARGO_REGISTRIES_<NAME>_TOKEN`, or `CARGO_REGISTRY_TOKEN` for crates.io
+    fn env_token(&self) -> Option<String> {
+        let var = if self.name == CRATES_IO_REGISTRY {
+            "CARGO_REGISTRY_TOKEN".to_owned()
+        } else {
+            format!(
+                "CARGO_REGISTRIES_{}_TOKEN",
+                self.name.to_uppercase().replace('-', "_")
+            )
+        };
+        std::env::var(var).ok().filter(|token| !token.is_empty())
+    }
+
+    /// Run an external credential provider with the `--cargo-plugin` protocol
+    fn plugin(&self, program: &str, args: &[String]) -> CargoResult<Option<Token>> {
+        let mut child = Command::new(program)
+            .args(args)
+            .arg("--cargo-plugin")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| {
+                format!(
+                    "Failed to run credential provider `{}` of registry `{}`",
+                    program, self.name
+                )
+            })?;
+        let invalid = || {
+            anyhow::format_err!(
+                "Invalid response from credential provider `{}` of registry `{}`",
+                program,
+                self.name
+            )
+        };
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let mut line = String::new();
+        stdout.read_line(&mut line)?;
+        let hello: PluginHello = serde_json::from_str(&line).map_err(|_| invalid())?;
+        if !hello.v.contains(&1) {
+            anyhow::bail!(
+                "The credential provider `{}` of registry `{}` doesn't support version 1 of the protocol",
+                program,
+                self.name
+            );
+        }
+        let request = serde_json::json!({
+            "v": 1,
+            "registry": {"index-url": self.index_url, "name": self.name},
+            "kind": "get",
+            "operation": "read",
+            "args": [],
+        });
+        writeln!(stdin, "{}", request)?;
+        line.clear();
+        stdout.read_line(&mut line)?;
+        drop(stdin);
+        let _ = child.wait();
+
+        match serde_json::from_str(&line).map_err(|_| invalid())? {
+            PluginResponse::Ok { token } => Ok(Some(Token(token))),
+            PluginResponse::Err { kind, .. }
+                if kind == "not-found" || kind == "url-not-supported" =>
+            {
+                Ok(None)
+            }
+            PluginResponse::Err { message, .. } => anyhow::bail!(
+                "The credential provider `{}` of registry `{}` failed: {}",
+                program,
+                self.name,
+                message.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Credentials {
+    /// Credentials of a registry with a token in its config
+    pub(crate) fn with_token(name: &str, token: &str) -> Self {
+        Credentials {
+            name: name.to_owned(),
+            index_url: String::new(),
+            configured_token: Some(token.to_owned()),
+            providers: vec![vec!["cargo:token".to_owned()]],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PluginHello {
+    v: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+enum PluginResponse {
+    Ok {
+        token: String,
+    },
+    Err {
+        kind: String,
+        message: Option<String>,
+    },
+}
+
+fn token_from_stdout(registry: &str, args: &[String]) -> CargoResult<Token> {
+    let (program, args) = args.split_first().with_context(|| {
+        format!(
+            "`cargo:token-from-stdout` of registry `{}` needs a command",
+            registry
+        )
+    })?;
+    let output = Command::new(program)
+        .args(args)
+        .env("CARGO_REGISTRY_NAME_OPT", registry)
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| {
+            format!(
+                "Failed to run `{}` for the token of registry `{}`",
+                program, registry
+            )
+        })?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`{}` failed to provide the token of registry `{}`: {}",
+            program,
+            registry,
+            output.status
+        );
+    }
+    let token = String::from_utf8(output.stdout)
+        .ok()
+        .map(|token| token.trim().to_owned())
+        .filter(|token| !token.is_empty())
+        .with_context(|| {
+            format!(
+                "`{}` provided no token for registry `{}`",
+                program, registry
+            )
+        })?;
+    Ok(Token(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::read_config_file;
+    use assert_fs::prelude::*;
+
+    fn credentials_in(root: &assert_fs::TempDir, url: &str) -> Option<Credentials> {
+        let mut config = Config::default();
+        read_config_file(&mut config, root.child("credentials.toml").path()).unwrap();
+        read_config_file(&mut config, root.child(".cargo/config.toml").path()).unwrap();
+        credentials(&config, &Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn token_from_config() {
+        let root = assert_fs::TempDir::new().unwrap();
+        root.child(".cargo/config.toml")
+            .write_str(
+                r#"
+[registries.internal]
+index = "sparse+https://internal.example.com/index/"
+token = "config-token"
+
+[registries.other]
+index = "https://other.example.com/index"
+"#,
+            )
+            .unwrap();
+        root.child("credentials.toml")
+            .write_str(
+                r#"
+[registries.other]
+token = "credentials-token"
+"#,
+            )
+            .unwrap();
+
+        let internal = credentials_in(&root, "sparse+https://internal.example.com/index").unwrap();
+        assert_eq!(internal.name(), "internal");
+        assert_eq!(
+            internal.token().unwrap(),
+            Some(Token("config-token".to_owned()))
+        );
+        let other = credentials_in(&root, "https://other.example.com/index/").unwrap();
+        assert_eq!(
+            other.token().unwrap(),
+            Some(Token("credentials-token".to_owned()))
+        );
+        assert!(credentials_in(&root, "https://unknown.example.com/index").is_none());
+    }
+
+    #[test]
+    fn token_from_env() {
+        let root = assert_fs::TempDir::new().unwrap();
+        root.child(".cargo/config.toml")
+            .write_str(
+                r#"
+[registries.my-registry]
+index = "https://my.example.com/index"
+token = "config-token"
+"#,
+            )
+            .unwrap();
+        root.child("credentials.toml").write_str("").unwrap();
+
+        std::env::set_var("CARGO_REGISTRIES_MY_REGISTRY_TOKEN", "env-token");
+        let token = credentials_in(&root, "https://my.example.com/index")
+            .unwrap()
+            .token()
+            .unwrap();
+        std::env::remove_var("CARGO_REGISTRIES_MY_REGISTRY_TOKEN");
+        assert_eq!(token, Some(Token("env-token".to_owned())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn token_from_provider() {
+        let root = assert_fs::TempDir::new().unwrap();
+        root.child(".cargo/config.toml")
+            .write_str(
+                r#"
+[registry]
+global-credential-providers = ["cargo:token", "stdout"]
+
+[credential-alias]
+stdout = ["cargo:token-from-stdout", "echo", "stdout-token"]
+
+[registries.internal]
+index = "https://internal.example.com/index"
+token = "config-token"
+
+[registries.unsupported]
+index = "https://unsupported.example.com/index"
+credential-provider = "cargo:libsecret"
+"#,
+            )
+            .unwrap();
+        root.child("credentials.toml").write_str("").unwrap();
+
+        let token = credentials_in(&root, "https://internal.example.com/index")
+            .unwrap()
+            .token()
+            .unwrap();
+        assert_eq!(token, Some(Token("stdout-token".to_owned())));
+        assert!(
+            credentials_in(&root, "https://unsupported.example.com/index")
+                .unwrap()
+                .token()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn redact_token() {
+        let token = Token("secret".to_owned());
+        assert!(!format!("{:?}", token).contains("secret"));
+        assert!(!format!("{:?}", Some(token)).contains("secret"));
+    }
+}
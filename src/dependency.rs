@@ -16,11 +16,44 @@ pub struct Dependency {
     /// If the dependency is renamed, this is the new name for the dependency
     /// as a string.  None if it is not renamed.
     rename: Option<String>,
+    public: Option<bool>,
+    /// Artifacts of the dependency to build, like `bin` or `cdylib`
+    artifact: Option<Vec<String>>,
+    /// Whether the library of an artifact dependency is used as well
+    lib: Option<bool>,
+    /// Target platform to build an artifact dependency for
+    target: Option<String>,
+    /// Keys not modeled here, kept as their TOML representation
+    extra: BTreeMap<String, String>,
 
     /// Features that are exposed by the dependency
     pub available_features: BTreeMap<String, Vec<String>>,
+    /// Dependencies of the dependency its features can refer to, and whether they are optional
+    pub available_dependencies: BTreeMap<String, bool>,
 }
 
+/// Keys of a dependency entry understood by [`Dependency`]
+const KNOWN_KEYS: &[&str] = &[
+    "version",
+    "path",
+    "registry",
+    "registry-index",
+    "git",
+    "branch",
+    "tag",
+    "rev",
+    "workspace",
+    "package",
+    "default-features",
+    "default_features",
+    "features",
+    "optional",
+    "public",
+    "artifact",
+    "lib",
+    "target",
+];
+
 impl Dependency {
     /// Create a new dependency with a name
     pub fn new(name: &str) -> Dependency {
@@ -36,14 +69,20 @@ impl Dependency {
         // store in the cargo toml files.  This would cause a warning upon compilation
         // ("version requirement […] includes semver metadata which will be ignored")
         let version = version.split('+').next().unwrap();
-        let (old_path, old_registry) = match self.source {
-            DependencySource::Version { path, registry, .. } => (path, registry),
-            _ => (None, None),
+        let (old_path, old_registry, old_registry_index) = match self.source {
+            DependencySource::Version {
+                path,
+                registry,
+                registry_index,
+                ..
+            } => (path, registry, registry_index),
+            _ => (None, None, None),
         };
         self.source = DependencySource::Version {
             version: Some(version.into()),
             path: old_path,
             registry: old_registry,
+            registry_index: old_registry_index,
         };
         self
     }
@@ -51,11 +90,15 @@ impl Dependency {
     /// Remove the existing version requirement
     pub fn clear_version(mut self) -> Dependency {
         if let DependencySource::Version {
-            version, registry, ..
+            version,
+            registry,
+            registry_index,
+            ..
         } = &mut self.source
         {
             *version = None;
             *registry = None;
+            *registry_index = None;
         }
         self
     }
@@ -69,6 +112,15 @@ impl Dependency {
         self
     }
 
+    /// Set the dependencies the features of the dependency can refer to
+    pub fn set_available_dependencies(
+        mut self,
+        available_dependencies: BTreeMap<String, bool>,
+    ) -> Dependency {
+        self.available_dependencies = available_dependencies;
+        self
+    }
+
     /// Set dependency to a given repository
     pub fn set_git(
         mut self,
@@ -97,20 +149,30 @@ impl Dependency {
             "Absolute path needed, got: {}",
             path.display()
         );
-        let (old_version, old_registry) = match self.source {
+        let (old_version, old_registry, old_registry_index) = match self.source {
             DependencySource::Version {
-                version, registry, ..
-            } => (version, registry),
-            _ => (None, None),
+                version,
+                registry,
+                registry_index,
+                ..
+            } => (version, registry, registry_index),
+            _ => (None, None, None),
         };
         self.source = DependencySource::Version {
             version: old_version,
             path: Some(path),
             registry: old_registry,
+            registry_index: old_registry_index,
         };
         self
     }
 
+    /// Set dependency to be inherited from the workspace's `[workspace.dependencies]`
+    pub fn set_workspace(mut self) -> Dependency {
+        self.source = DependencySource::Workspace;
+        self
+    }
+
     /// Set whether the dependency is optional
     pub fn set_optional(mut self, opt: Option<bool>) -> Dependency {
         self.optional = opt;
@@ -144,10 +206,50 @@ impl Dependency {
             version: old_version,
             path: old_path,
             registry: Some(registry.into()),
+            registry_index: None,
         };
         self
     }
 
+    /// Set the index URL of the registry for the dependency
+    pub fn set_registry_index(mut self, registry_index: &str) -> Dependency {
+        let (old_version, old_path) = match self.source {
+            DependencySource::Version { version, path, .. } => (version, path),
+            _ => (None, None),
+        };
+        self.source = DependencySource::Version {
+            version: old_version,
+            path: old_path,
+            registry: None,
+            registry_index: Some(registry_index.into()),
+        };
+        self
+    }
+
+    /// Set whether the dependency is public
+    pub fn set_public(mut self, public: Option<bool>) -> Dependency {
+        self.public = public;
+        self
+    }
+
+    /// Set the artifacts to build for an artifact dependency
+    pub fn set_artifact(mut self, artifact: Option<Vec<String>>) -> Dependency {
+        self.artifact = artifact;
+        self
+    }
+
+    /// Set whether the library of an artifact dependency is used as well
+    pub fn set_lib(mut self, lib: Option<bool>) -> Dependency {
+        self.lib = lib;
+        self
+    }
+
+    /// Set the target platform to build an artifact dependency for
+    pub fn set_artifact_target(mut self, target: Option<String>) -> Dependency {
+        self.target = target;
+        self
+    }
+
     /// Get version of dependency
     pub fn version(&self) -> Option<&str> {
         if let DependencySource::Version {
@@ -187,6 +289,19 @@ impl Dependency {
         }
     }
 
+    /// Get the registry index URL of the dependency
+    pub fn registry_index(&self) -> Option<&str> {
+        if let DependencySource::Version {
+            registry_index: Some(ref registry_index),
+            ..
+        } = self.source
+        {
+            Some(registry_index)
+        } else {
+            None
+        }
+    }
+
     /// Get the git repo of the dependency
     pub fn git(&self) -> Option<&str> {
         if let DependencySource::Git { repo, .. } = &self.source {
@@ -196,6 +311,11 @@ impl Dependency {
         }
     }
 
+    /// Whether the dependency is inherited from the workspace (`workspace = true`)
+    pub fn is_workspace(&self) -> bool {
+        matches!(self.source, DependencySource::Workspace)
+    }
+
     /// Get the alias for the dependency (if any)
     pub fn rename(&self) -> Option<&str> {
         self.rename.as_deref()
@@ -205,6 +325,81 @@ impl Dependency {
     pub fn default_features(&self) -> Option<bool> {
         self.default_features
     }
+
+    /// Whether the dependency is public
+    pub fn public(&self) -> Option<bool> {
+        self.public
+    }
+
+    /// Get the artifacts to build for an artifact dependency
+    pub fn artifact(&self) -> Option<&[String]> {
+        self.artifact.as_deref()
+    }
+
+    /// Whether the library of an artifact dependency is used as well
+    pub fn lib(&self) -> Option<bool> {
+        self.lib
+    }
+
+    /// Get the target platform to build an artifact dependency for
+    pub fn artifact_target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Resolve an inherited dependency against its `[workspace.dependencies]` entry
+    ///
+    /// The source, `default-features` and package name come from the workspace, `optional` comes
+    /// from the member and `features` are the union of both.
+    pub fn inherit(mut self, workspace_dep: &Dependency) -> Dependency {
+        if !self.is_workspace() {
+            return self;
+        }
+
+        self.name = workspace_dep.name.clone();
+        self.rename = workspace_dep.rename.clone();
+        self.source = workspace_dep.source.clone();
+        self.default_features = workspace_dep.default_features;
+        self.artifact = self.artifact.or_else(|| workspace_dep.artifact.clone());
+        self.lib = self.lib.or(workspace_dep.lib);
+        self.target = self.target.or_else(|| workspace_dep.target.clone());
+        if let Some(workspace_features) = &workspace_dep.features {
+            let mut features: indexmap::IndexSet<_> = workspace_features.iter().cloned().collect();
+            features.extend(self.features.take().into_iter().flatten());
+            self.features = Some(features.into_iter().collect());
+        }
+        if self.available_features.is_empty() {
+            self.available_features = workspace_dep.available_features.clone();
+        }
+        if self.available_dependencies.is_empty() {
+            self.available_dependencies = workspace_dep.available_dependencies.clone();
+        }
+        self
+    }
+
+    /// Split into a `[workspace.dependencies]` entry and a member entry inheriting from it
+    ///
+    /// The workspace entry gets the source, `default-features`, any rename and the artifact keys
+    /// while everything else, like `features` and `optional`, stays with the member.
+    pub fn split_workspace(&self) -> (Dependency, Dependency) {
+        let workspace_dep = Dependency {
+            name: self.name.clone(),
+            rename: self.rename.clone(),
+            source: self.source.clone(),
+            default_features: self.default_features,
+            artifact: self.artifact.clone(),
+            lib: self.lib,
+            target: self.target.clone(),
+            ..Dependency::default()
+        };
+        let member_dep = self
+            .clone()
+            .set_workspace()
+            .set_default_features(None)
+            .set_artifact(None)
+            .set_lib(None)
+            .set_artifact_target(None);
+        (workspace_dep, member_dep)
+    }
 }
 
 impl Dependency {
@@ -220,7 +415,13 @@ impl Dependency {
                 (key.to_owned(), None)
             };
 
-            let source = if let Some(repo) = table.get("git") {
+            let source = if let Some(value) = table.get("workspace") {
+                // `workspace = false` is not valid
+                if !value.as_bool()? {
+                    return None;
+                }
+                DependencySource::Workspace
+            } else if let Some(repo) = table.get("git") {
                 let repo = repo.as_str()?.to_owned();
                 let branch = if let Some(value) = table.get("branch") {
                     Some(value.as_str()?.to_owned())
@@ -261,14 +462,23 @@ impl Dependency {
                 } else {
                     None
                 };
+                let registry_index = if let Some(value) = table.get("registry-index") {
+                    Some(value.as_str()?.to_owned())
+                } else {
+                    None
+                };
                 DependencySource::Version {
                     version,
                     path,
                     registry,
+                    registry_index,
                 }
             };
 
-            let default_features = if let Some(value) = table.get("default-features") {
+            let default_features = if let Some(value) = table
+                .get("default-features")
+                .or_else(|| table.get("default_features"))
+            {
                 value.as_bool()?
             } else {
                 true
@@ -296,6 +506,39 @@ impl Dependency {
             };
             let optional = Some(optional);
 
+            let public = if let Some(value) = table.get("public") {
+                Some(value.as_bool()?)
+            } else {
+                None
+            };
+
+            let artifact = if let Some(value) = table.get("artifact") {
+                Some(artifact_from_toml(value)?)
+            } else {
+                None
+            };
+
+            let lib = if let Some(value) = table.get("lib") {
+                Some(value.as_bool()?)
+            } else {
+                None
+            };
+
+            let target = if let Some(value) = table.get("target") {
+                Some(value.as_str()?.to_owned())
+            } else {
+                None
+            };
+
+            let extra = table
+                .iter()
+                .filter(|(key, _)| !KNOWN_KEYS.contains(key))
+                .filter_map(|(key, item)| {
+                    let value = item.clone().into_value().ok()?;
+                    Some((key.to_owned(), value.to_string().trim().to_owned()))
+                })
+                .collect();
+
             let dep = Dependency {
                 name,
                 rename,
@@ -303,7 +546,13 @@ impl Dependency {
                 default_features,
                 features,
                 available_features,
+                available_dependencies: BTreeMap::default(),
                 optional,
+                public,
+                artifact,
+                lib,
+                target,
+                extra,
             };
             Some(dep)
         } else {
@@ -350,9 +599,17 @@ impl Dependency {
                     version: Some(v),
                     path: None,
                     registry: None,
+                    registry_index: None,
                 },
                 None,
-            ) => toml_edit::value(v),
+            ) if self.public.is_none()
+                && self.artifact.is_none()
+                && self.lib.is_none()
+                && self.target.is_none()
+                && self.extra.is_empty() =>
+            {
+                toml_edit::value(v)
+            }
             // Other cases are represented as an inline table
             (_, _, _, _, _) => {
                 let mut data = toml_edit::InlineTable::default();
@@ -362,6 +619,7 @@ impl Dependency {
                         version,
                         path,
                         registry,
+                        registry_index,
                     } => {
                         if let Some(v) = version {
                             data.insert("version", v.into());
@@ -373,6 +631,9 @@ impl Dependency {
                         if let Some(r) = registry {
                             data.insert("registry", r.into());
                         }
+                        if let Some(r) = registry_index {
+                            data.insert("registry-index", r.into());
+                        }
                     }
                     DependencySource::Git {
                         repo,
@@ -391,8 +652,11 @@ impl Dependency {
                             data.insert("rev", rev.into());
                         }
                     }
+                    DependencySource::Workspace => {
+                        data.insert("workspace", true.into());
+                    }
                 }
-                if self.rename.is_some() {
+                if self.rename.is_some() && !self.is_workspace() {
                     data.insert("package", self.name.as_str().into());
                 }
                 match self.default_features {
@@ -411,6 +675,23 @@ impl Dependency {
                         data.insert("optional", true.into());
                     }
                 }
+                if let Some(public) = self.public {
+                    data.insert("public", public.into());
+                }
+                if let Some(artifact) = self.artifact.as_deref() {
+                    data.insert("artifact", artifact_value(artifact));
+                }
+                if let Some(lib) = self.lib {
+                    data.insert("lib", lib.into());
+                }
+                if let Some(target) = self.target.as_deref() {
+                    data.insert("target", target.into());
+                }
+                for (key, value) in &self.extra {
+                    if let Ok(value) = value.parse::<toml_edit::Value>() {
+                        data.insert(key, value);
+                    }
+                }
 
                 toml_edit::value(toml_edit::Value::InlineTable(data))
             }
@@ -434,6 +715,7 @@ impl Dependency {
                     version,
                     path,
                     registry,
+                    registry_index,
                 } => {
                     if let Some(v) = version {
                         table.insert("version", toml_edit::value(v));
@@ -448,8 +730,13 @@ impl Dependency {
                     }
                     if let Some(r) = registry {
                         table.insert("registry", toml_edit::value(r));
+                        table.remove("registry-index");
+                    }
+                    if let Some(r) = registry_index {
+                        table.insert("registry-index", toml_edit::value(r));
+                        table.remove("registry");
                     }
-                    for key in ["git", "branch", "tag", "rev"] {
+                    for key in ["git", "branch", "tag", "rev", "workspace"] {
                         table.remove(key);
                     }
                 }
@@ -475,19 +762,37 @@ impl Dependency {
                     } else {
                         table.remove("rev");
                     }
-                    for key in ["version", "path", "registry"] {
+                    for key in ["version", "path", "registry", "registry-index", "workspace"] {
+                        table.remove(key);
+                    }
+                }
+                DependencySource::Workspace => {
+                    table.insert("workspace", toml_edit::value(true));
+                    for key in [
+                        "version",
+                        "path",
+                        "registry",
+                        "registry-index",
+                        "git",
+                        "branch",
+                        "tag",
+                        "rev",
+                        "package",
+                    ] {
                         table.remove(key);
                     }
                 }
             }
-            if self.rename.is_some() {
+            if self.rename.is_some() && !self.is_workspace() {
                 table.insert("package", toml_edit::value(self.name.as_str()));
             }
             match self.default_features {
                 Some(true) => {
                     table.remove("default-features");
+                    table.remove("default_features");
                 }
                 Some(false) => {
+                    table.remove("default_features");
                     table.insert("default-features", toml_edit::value(false));
                 }
                 None => {}
@@ -516,6 +821,31 @@ impl Dependency {
                 }
                 None => {}
             }
+            if let Some(public) = self.public {
+                table.insert("public", toml_edit::value(public));
+            }
+            if let Some(artifact) = self.artifact.as_deref() {
+                let unchanged = table
+                    .get("artifact")
+                    .and_then(artifact_from_toml)
+                    .is_some_and(|existing| existing == artifact);
+                if !unchanged {
+                    table.insert("artifact", toml_edit::value(artifact_value(artifact)));
+                }
+            }
+            if let Some(lib) = self.lib {
+                table.insert("lib", toml_edit::value(lib));
+            }
+            if let Some(target) = self.target.as_deref() {
+                table.insert("target", toml_edit::value(target));
+            }
+            for (key, value) in &self.extra {
+                if !table.contains_key(key) {
+                    if let Ok(value) = value.parse::<toml_edit::Value>() {
+                        table.insert(key, toml_edit::value(value));
+                    }
+                }
+            }
 
             table.fmt();
         } else {
@@ -530,6 +860,25 @@ fn path_field(crate_root: &Path, abs_path: &Path) -> String {
     relpath
 }
 
+/// Like `features` but Cargo also accepts a single string
+fn artifact_from_toml(item: &toml_edit::Item) -> Option<Vec<String>> {
+    if let Some(artifact) = item.as_str() {
+        Some(vec![artifact.to_owned()])
+    } else {
+        item.as_array()?
+            .iter()
+            .map(|v| v.as_str().map(|s| s.to_owned()))
+            .collect()
+    }
+}
+
+fn artifact_value(artifact: &[String]) -> toml_edit::Value {
+    match artifact {
+        [artifact] => artifact.into(),
+        _ => artifact.iter().cloned().collect(),
+    }
+}
+
 fn is_package_eq(item: &mut toml_edit::Item, name: &str, rename: Option<&str>) -> bool {
     if let Some(table) = item.as_table_like_mut() {
         let existing_package = table.get("package").and_then(|i| i.as_str());
@@ -552,8 +901,15 @@ impl Default for Dependency {
                 version: None,
                 path: None,
                 registry: None,
+                registry_index: None,
             },
+            public: None,
+            artifact: None,
+            lib: None,
+            target: None,
+            extra: BTreeMap::default(),
             available_features: BTreeMap::default(),
+            available_dependencies: BTreeMap::default(),
         }
     }
 }
@@ -564,6 +920,7 @@ enum DependencySource {
         version: Option<String>,
         path: Option<PathBuf>,
         registry: Option<String>,
+        registry_index: Option<String>,
     },
     Git {
         repo: String,
@@ -571,6 +928,7 @@ enum DependencySource {
         tag: Option<String>,
         rev: Option<String>,
     },
+    Workspace,
 }
 
 #[cfg(test)]
@@ -724,6 +1082,213 @@ mod tests {
         verify_roundtrip(&crate_root, key, &item);
     }
 
+    #[test]
+    fn to_toml_dep_from_workspace() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let dep = Dependency::new("dep")
+            .set_workspace()
+            .set_features(Some(vec!["std".to_owned()]))
+            .set_optional(Some(true));
+        let key = dep.toml_key();
+        let item = dep.to_toml(&crate_root);
+
+        assert_eq!(key, "dep".to_owned());
+        assert!(item.is_inline_table());
+
+        let dep = item.as_inline_table().unwrap();
+        assert_eq!(dep.get("workspace").unwrap().as_bool(), Some(true));
+        assert!(dep.get("version").is_none());
+
+        verify_roundtrip(&crate_root, key, &item);
+    }
+
+    #[test]
+    fn to_toml_dep_with_registry_index() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let dep = Dependency::new("dep")
+            .set_version("1.0")
+            .set_registry_index("https://example.com/index");
+        let key = dep.toml_key();
+        let item = dep.to_toml(&crate_root);
+
+        let table = item.as_inline_table().unwrap();
+        assert_eq!(
+            table.get("registry-index").unwrap().as_str(),
+            Some("https://example.com/index")
+        );
+        assert!(table.get("registry").is_none());
+
+        verify_roundtrip(&crate_root, key, &item);
+    }
+
+    #[test]
+    fn to_toml_public_dep() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let dep = Dependency::new("dep")
+            .set_version("1.0")
+            .set_public(Some(true));
+        let key = dep.toml_key();
+        let item = dep.to_toml(&crate_root);
+
+        let table = item.as_inline_table().unwrap();
+        assert_eq!(table.get("public").unwrap().as_bool(), Some(true));
+
+        verify_roundtrip(&crate_root, key, &item);
+    }
+
+    #[test]
+    fn to_toml_artifact_dep() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let dep = Dependency::new("dep")
+            .set_version("1.0")
+            .set_artifact(Some(vec!["bin".to_owned()]))
+            .set_lib(Some(true))
+            .set_artifact_target(Some("wasm32-unknown-unknown".to_owned()));
+        let key = dep.toml_key();
+        let item = dep.to_toml(&crate_root);
+
+        let table = item.as_inline_table().unwrap();
+        assert_eq!(table.get("artifact").unwrap().as_str(), Some("bin"));
+        assert_eq!(table.get("lib").unwrap().as_bool(), Some(true));
+        assert_eq!(
+            table.get("target").unwrap().as_str(),
+            Some("wasm32-unknown-unknown")
+        );
+
+        verify_roundtrip(&crate_root, key, &item);
+    }
+
+    #[test]
+    fn from_toml_artifact_list() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let item = toml_edit::value(
+            "{ version = \"1.0\", artifact = [\"bin:foo\", \"cdylib\"] }"
+                .parse::<toml_edit::Value>()
+                .unwrap(),
+        );
+        let dep = Dependency::from_toml(&crate_root, "dep", &item).unwrap();
+        assert_eq!(
+            dep.artifact(),
+            Some(&["bin:foo".to_owned(), "cdylib".to_owned()][..])
+        );
+
+        verify_roundtrip(&crate_root, "dep", &item);
+    }
+
+    #[test]
+    fn from_toml_default_features_alias() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let item = toml_edit::value(
+            "{ version = \"1.0\", default_features = false }"
+                .parse::<toml_edit::Value>()
+                .unwrap(),
+        );
+        let dep = Dependency::from_toml(&crate_root, "dep", &item).unwrap();
+        assert_eq!(dep.default_features(), Some(false));
+
+        let mut item = item;
+        dep.update_toml(&crate_root, &mut item);
+        let table = item.as_inline_table().unwrap();
+        assert_eq!(
+            table.get("default-features").unwrap().as_bool(),
+            Some(false)
+        );
+        assert!(table.get("default_features").is_none());
+    }
+
+    #[test]
+    fn to_toml_keeps_unknown_keys() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let item = toml_edit::value(
+            "{ version = \"1.0\", future-key = { nested = [1, 2] } }"
+                .parse::<toml_edit::Value>()
+                .unwrap(),
+        );
+        let dep = Dependency::from_toml(&crate_root, "dep", &item)
+            .unwrap()
+            .set_version("2.0");
+        let item = dep.to_toml(&crate_root);
+
+        let table = item.as_inline_table().unwrap();
+        assert_eq!(table.get("version").unwrap().as_str(), Some("2.0"));
+        assert_eq!(
+            table.get("future-key").unwrap().to_string().trim(),
+            "{ nested = [1, 2] }"
+        );
+    }
+
+    #[test]
+    fn update_toml_switches_registry() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let mut item: toml_edit::Item = toml_edit::value(
+            "{ version = \"1.0\", registry = \"alternative\", public = true }"
+                .parse::<toml_edit::Value>()
+                .unwrap(),
+        );
+        let dep = Dependency::new("dep")
+            .set_version("1.0")
+            .set_registry_index("https://example.com/index");
+        dep.update_toml(&crate_root, &mut item);
+
+        let table = item.as_inline_table().unwrap();
+        assert!(table.get("registry").is_none());
+        assert_eq!(
+            table.get("registry-index").unwrap().as_str(),
+            Some("https://example.com/index")
+        );
+        assert_eq!(table.get("public").unwrap().as_bool(), Some(true));
+    }
+
+    #[test]
+    fn update_toml_version_to_workspace() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let mut item: toml_edit::Item = toml_edit::value(
+            "{ version = \"1.0\", features = [\"std\"] }"
+                .parse::<toml_edit::Value>()
+                .unwrap(),
+        );
+        let dep = Dependency::new("dep").set_workspace();
+        dep.update_toml(&crate_root, &mut item);
+
+        let table = item.as_inline_table().unwrap();
+        assert_eq!(table.get("workspace").unwrap().as_bool(), Some(true));
+        assert!(table.get("version").is_none());
+        assert!(table.get("features").is_some());
+    }
+
+    #[test]
+    fn inherit_from_workspace() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let workspace_dep = Dependency::new("dep")
+            .set_version("1.0")
+            .set_default_features(Some(false))
+            .set_features(Some(vec!["std".to_owned()]));
+        let member_dep = Dependency::new("dep")
+            .set_workspace()
+            .set_optional(Some(true))
+            .set_features(Some(vec!["derive".to_owned(), "std".to_owned()]));
+
+        let resolved = member_dep.inherit(&workspace_dep);
+        assert!(!resolved.is_workspace());
+        assert_eq!(resolved.version(), Some("1.0"));
+        assert_eq!(resolved.default_features(), Some(false));
+        assert_eq!(
+            resolved.features,
+            Some(vec!["std".to_owned(), "derive".to_owned()])
+        );
+
+        let item = resolved.to_toml(&crate_root);
+        let table = item.as_inline_table().unwrap();
+        assert_eq!(table.get("optional").unwrap().as_bool(), Some(true));
+    }
+
+    #[test]
+    fn invalid_workspace_false() {
+        let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let item = toml_edit::value("{ workspace = false }".parse::<toml_edit::Value>().unwrap());
+        assert!(Dependency::from_toml(&crate_root, "dep", &item).is_none());
+    }
+
     #[test]
     fn paths_with_forward_slashes_are_left_as_is() {
         let crate_root = dunce::canonicalize(Path::new("/")).expect("root exists");
@@ -69,6 +69,14 @@ pub(crate) fn no_crate_err(name: impl Display) -> Error {
     anyhow::format_err!("The crate `{}` could not be found in registry index.", name)
 }
 
+pub(crate) fn not_in_offline_cache_err(name: impl Display, registry: impl Display) -> Error {
+    anyhow::format_err!(
+        "The crate `{}` could not be found in the local cache of registry `{}`, run without `--offline` to fetch it.",
+        name,
+        registry
+    )
+}
+
 pub(crate) fn parse_manifest_err() -> Error {
     anyhow::format_err!("Unable to parse external Cargo.toml")
 }
@@ -85,6 +93,25 @@ pub(crate) fn non_existent_dependency_err(name: impl Display, table: impl Displa
     )
 }
 
+pub(crate) fn non_existent_feature_err(name: impl Display) -> Error {
+    anyhow::format_err!("The feature `{}` could not be found in `features`.", name)
+}
+
+pub(crate) fn no_workspace_root_err(name: impl Display, manifest: impl Display) -> Error {
+    anyhow::format_err!(
+        "The dependency `{}` is inherited from the workspace, but no workspace root was found for {}",
+        name,
+        manifest,
+    )
+}
+
+pub(crate) fn inherited_rust_version_err(manifest: impl Display) -> Error {
+    anyhow::format_err!(
+        "The rust-version of {} is inherited from the workspace, but no workspace root was found",
+        manifest,
+    )
+}
+
 pub(crate) fn invalid_cargo_config() -> Error {
     anyhow::format_err!("Invalid cargo config")
 }
@@ -1,16 +1,22 @@
-use std::collections::BTreeMap;
-use std::env;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::Duration;
 
 use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
 use url::Url;
 
+use super::credentials::{registry_credentials, Token};
 use super::errors::*;
-use super::registry::registry_url;
-use super::VersionExt;
+use super::registry::{
+    cache_dir, cargo_home, cargo_registry_dirs, registry_url, source_kind, GitReference, Kind,
+    CRATES_IO_REGISTRY,
+};
+use super::source::{index_file_versions, CrateVersion, FixtureRegistry, GitIndex, RegistrySource};
+use super::sparse::SparseIndex;
 use super::{Dependency, LocalManifest, Manifest};
+use super::{RustVersion, VersionExt};
 use regex::Regex;
 
 /// Query latest version from a registry index
@@ -21,57 +27,65 @@ use regex::Regex;
 /// The latest version will be returned as a `Dependency`. This will fail, when
 ///
 /// - there is no Internet connection and offline is false.
+/// - offline is true and the crate isn't in Cargo's local cache of the registry.
 /// - summaries in registry index with an incorrect format.
 /// - a crate with the given name does not exist on the registry.
+///
+/// With a `rust_version`, the latest version supporting it is picked when there is one.
 pub fn get_latest_dependency(
     crate_name: &str,
     flag_allow_prerelease: bool,
     manifest_path: &Path,
     registry: Option<&Url>,
+    offline: bool,
+    rust_version: Option<RustVersion>,
 ) -> CargoResult<Dependency> {
-    if env::var("CARGO_IS_TEST").is_ok() {
-        // We are in a simulated reality. Nothing is real here.
-        // FIXME: Use actual test handling code.
-        let new_version = if flag_allow_prerelease {
-            format!("99999.0.0-alpha.1+{}", crate_name)
-        } else {
-            match crate_name {
-                "test_breaking" => "0.2.0".to_string(),
-                "test_nonbreaking" => "0.1.1".to_string(),
-                other => format!("99999.0.0+{}", other),
-            }
-        };
-
-        let features = if crate_name == "your-face" {
-            [
-                ("nose".to_string(), vec![]),
-                ("mouth".to_string(), vec![]),
-                ("eyes".to_string(), vec![]),
-                ("ears".to_string(), vec![]),
-            ]
-            .into_iter()
-            .collect::<BTreeMap<_, _>>()
-        } else {
-            BTreeMap::default()
-        };
+    let registry = match registry {
+        Some(url) => url.clone(),
+        None => registry_url(manifest_path, None)?,
+    };
 
-        return Ok(Dependency::new(crate_name)
-            .set_version(&new_version)
-            .set_available_features(features));
-    }
+    let source = open_registry_source(&registry, manifest_path, offline)?;
+    get_latest_dependency_from(
+        source.as_ref(),
+        crate_name,
+        flag_allow_prerelease,
+        rust_version,
+    )
+}
 
+/// Query the latest version of a crate from `source`
+///
+/// Yanked versions are skipped, as are pre-releases unless `flag_allow_prerelease` is set, and
+/// versions requiring a newer Rust than `rust_version` unless none supports it. When
+/// `crate_name` isn't found, its variants with `-` and `_` swapped are tried.
+pub fn get_latest_dependency_from(
+    source: &dyn RegistrySource,
+    crate_name: &str,
+    flag_allow_prerelease: bool,
+    rust_version: Option<RustVersion>,
+) -> CargoResult<Dependency> {
     if crate_name.is_empty() {
         anyhow::bail!("Found empty crate name");
     }
 
-    let registry = match registry {
-        Some(url) => url.clone(),
-        None => registry_url(manifest_path, None)?,
-    };
-
-    let crate_versions = fuzzy_query_registry_index(crate_name, &registry)?;
+    let crate_versions = fuzzy_query_registry_index(crate_name, source)?;
+    latest_dependency(
+        &crate_versions,
+        crate_name,
+        flag_allow_prerelease,
+        rust_version,
+    )
+}
 
-    let dep = read_latest_version(&crate_versions, flag_allow_prerelease)?;
+/// The latest of the `crate_versions` found for `crate_name`
+pub(crate) fn latest_dependency(
+    crate_versions: &[CrateVersion],
+    crate_name: &str,
+    flag_allow_prerelease: bool,
+    rust_version: Option<RustVersion>,
+) -> CargoResult<Dependency> {
+    let dep = read_latest_version(crate_versions, flag_allow_prerelease, rust_version)?;
 
     if dep.name != crate_name {
         eprintln!("WARN: Added `{}` instead of `{}`", dep.name, crate_name);
@@ -80,21 +94,259 @@ pub fn get_latest_dependency(
     Ok(dep)
 }
 
-#[derive(Debug)]
-struct CrateVersion {
-    name: String,
-    version: semver::Version,
-    yanked: bool,
-    available_features: BTreeMap<String, Vec<String>>,
+/// Whether `registry` is a registry reached over the network, rather than a local source
+fn is_remote_registry(registry: &Url) -> CargoResult<bool> {
+    Ok(matches!(
+        source_kind(registry)?.0,
+        Kind::Registry | Kind::SparseRegistry
+    ))
+}
+
+/// Open the registry or source at `registry`, as returned by [`registry_url`] for
+/// `manifest_path`
+///
+/// When `offline`, remote registries are read from Cargo's local cache. When
+/// `CARGO_EDIT_FIXTURE_REGISTRY` is set, the [`FixtureRegistry`] it points at replaces every
+/// remote registry.
+pub fn open_registry_source(
+    registry: &Url,
+    manifest_path: &Path,
+    offline: bool,
+) -> CargoResult<Box<dyn RegistrySource>> {
+    if is_remote_registry(registry)? {
+        if let Some(fixture) = FixtureRegistry::from_env() {
+            return Ok(Box::new(fixture));
+        }
+    }
+    let (kind, location) = source_kind(registry)?;
+    let source: Box<dyn RegistrySource> = match kind {
+        Kind::Registry | Kind::SparseRegistry if offline => {
+            Box::new(LocalCache::open(registry, &kind, &location)?)
+        }
+        Kind::Registry | Kind::Path => Box::new(GitIndex::new(&git_index(
+            registry,
+            &location,
+            manifest_path,
+            false,
+        )?)?),
+        Kind::SparseRegistry => {
+            let credentials = registry_credentials(manifest_path, registry)?;
+            Box::new(SparseIndex::open(&location, credentials.as_ref())?)
+        }
+        Kind::LocalRegistry => Box::new(LocalRegistry(source_path(&location)?)),
+        Kind::Directory => Box::new(Packages(read_packages(&source_path(&location)?)?)),
+        Kind::Git(reference) => {
+            if offline && !is_checked_out(&cache_dir("git", &location)?) {
+                anyhow::bail!(
+                    "The git repository `{}` was never fetched, run without `--offline` to fetch it",
+                    location
+                );
+            }
+            let checkout = git_checkout(&location, &reference, false)?;
+            let mut packages: Vec<_> = read_package(&checkout.join("Cargo.toml"))?
+                .into_iter()
+                .collect();
+            read_nested_packages(&checkout, &mut packages)?;
+            Box::new(Packages(packages))
+        }
+    };
+    Ok(source)
+}
+
+/// The index of a `local-registry` source, next to the `.crate` files
+struct LocalRegistry(PathBuf);
+
+impl RegistrySource for LocalRegistry {
+    fn versions(&self, crate_name: &str) -> CargoResult<Option<Vec<CrateVersion>>> {
+        let path = match index_path(crate_name) {
+            Some(path) => self.0.join("index").join(path),
+            None => return Ok(None),
+        };
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let file =
+            std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        index_file_versions(crate_name, &file).map(Some)
+    }
+}
+
+/// The packages of a `directory` source or a git repository
+struct Packages(Vec<CrateVersion>);
+
+impl RegistrySource for Packages {
+    fn versions(&self, crate_name: &str) -> CargoResult<Option<Vec<CrateVersion>>> {
+        let versions: Vec<_> = self
+            .0
+            .iter()
+            .filter(|package| package.name.eq_ignore_ascii_case(crate_name))
+            .cloned()
+            .collect();
+        Ok((!versions.is_empty()).then_some(versions))
+    }
+}
+
+/// Cargo's local copies of a remote registry: its index cache, the downloaded `.crate` files and
+/// their extracted sources
+struct LocalCache {
+    /// URL of the registry, as returned by [`registry_url`]
+    registry: Url,
+    /// `$CARGO_HOME/registry`
+    root: PathBuf,
+    /// Names of the directories Cargo keeps for the registry in `index/`, `cache/` and `src/`
+    dirs: Vec<String>,
+}
+
+impl LocalCache {
+    fn open(registry: &Url, kind: &Kind, location: &Url) -> CargoResult<Self> {
+        Self::with_root(cargo_home()?.join("registry"), registry, kind, location)
+    }
+
+    fn with_root(root: PathBuf, registry: &Url, kind: &Kind, location: &Url) -> CargoResult<Self> {
+        let dirs: BTreeSet<_> = cargo_registry_dirs(kind, location)
+            .into_iter()
+            .filter(|dir| {
+                ["index", "cache"]
+                    .iter()
+                    .any(|subdir| root.join(subdir).join(dir).is_dir())
+            })
+            .collect();
+        if dirs.is_empty() {
+            anyhow::bail!(
+                "The registry `{}` was never downloaded, run without `--offline` to fetch it",
+                registry
+            );
+        }
+        stale_cache_message(registry)?;
+        Ok(LocalCache {
+            registry: registry.clone(),
+            root,
+            dirs: dirs.into_iter().collect(),
+        })
+    }
+}
+
+impl RegistrySource for LocalCache {
+    fn versions(&self, crate_name: &str) -> CargoResult<Option<Vec<CrateVersion>>> {
+        let mut versions = Vec::new();
+        if let Some(path) = index_path(crate_name) {
+            for dir in &self.dirs {
+                let path = self.root.join("index").join(dir).join(".cache").join(&path);
+                if let Some(file) = std::fs::read(&path)
+                    .ok()
+                    .and_then(|content| read_index_cache(&content))
+                {
+                    versions.extend(index_file_versions(crate_name, &file).unwrap_or_default());
+                }
+            }
+        }
+        // Versions downloaded before the index cache was last written, or from another index
+        // cache, may still be missing
+        for dir in &self.dirs {
+            let entries = std::fs::read_dir(self.root.join("cache").join(dir));
+            for entry in entries.into_iter().flatten() {
+                let file_name = entry?.file_name().to_string_lossy().into_owned();
+                let version = file_name
+                    .strip_suffix(".crate")
+                    .and_then(|stem| {
+                        Some((stem.get(..crate_name.len())?, stem.get(crate_name.len()..)?))
+                    })
+                    .filter(|(name, _)| name.eq_ignore_ascii_case(crate_name))
+                    .and_then(|(_, version)| version.strip_prefix('-'))
+                    .and_then(|version| semver::Version::parse(version).ok());
+                let version = match version {
+                    Some(version) => version,
+                    None => continue,
+                };
+                if versions.iter().any(|known| known.version == version) {
+                    continue;
+                }
+                let package = self
+                    .root
+                    .join("src")
+                    .join(dir)
+                    .join(&file_name[..file_name.len() - ".crate".len()])
+                    .join("Cargo.toml");
+                let crate_version = match read_package(&package)? {
+                    Some(package) => package,
+                    None => CrateVersion {
+                        name: file_name[..crate_name.len()].to_owned(),
+                        version,
+                        yanked: false,
+                        available_features: Default::default(),
+                        available_dependencies: Default::default(),
+                        rust_version: None,
+                    },
+                };
+                versions.push(crate_version);
+            }
+        }
+        if versions.is_empty() {
+            return Ok(None);
+        }
+        versions.sort_by(|a, b| a.version.cmp(&b.version));
+        versions.dedup_by(|a, b| a.version == b.version);
+        Ok(Some(versions))
+    }
+
+    fn crate_not_found(&self, crate_name: &str) -> Error {
+        not_in_offline_cache_err(crate_name, &self.registry)
+    }
+}
+
+/// Read an entry of the index cache Cargo keeps in `.cache`: a version byte, the version of the
+/// index format for recent versions, then NUL-separated fields starting with the revision of
+/// the index, followed by a version and its index line for each version
+fn read_index_cache(content: &[u8]) -> Option<Vec<u8>> {
+    let (cache_version, rest) = content.split_first()?;
+    let rest = match cache_version {
+        1 => rest,
+        3 => rest.get(4..)?,
+        _ => return None,
+    };
+    let mut fields = rest.split(|byte| *byte == 0).skip(1);
+    let mut lines = Vec::new();
+    while let (Some(_), Some(line)) = (fields.next(), fields.next()) {
+        lines.extend_from_slice(line);
+        lines.push(b'\n');
+    }
+    Some(lines)
+}
+
+/// Warn, once per registry, that versions published since Cargo last updated its copy of the
+/// registry are missing
+fn stale_cache_message(registry: &Url) -> CargoResult<()> {
+    static WARNED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+    if !WARNED.lock().unwrap().insert(registry.to_string()) {
+        return Ok(());
+    }
+    warning_message(format_args!(
+        "offline, reading Cargo's local cache of `{}` which may be missing recently published versions",
+        registry
+    ))
+}
+
+fn warning_message(message: std::fmt::Arguments<'_>) -> CargoResult<()> {
+    let mut output = StandardStream::stderr(super::colorize_stderr());
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true))?;
+    write!(output, "warning")?;
+    output.reset()?;
+    writeln!(output, ": {}", message)?;
+    Ok(())
+}
+
+/// Whether a git repository in cargo-edit's cache was ever checked out
+fn is_checked_out(dir: &Path) -> bool {
+    git2::Repository::open(dir)
+        .map(|repo| repo.head().is_ok())
+        .unwrap_or(false)
 }
 
 /// Fuzzy query crate from registry index
-fn fuzzy_query_registry_index(
+pub(crate) fn fuzzy_query_registry_index(
     crate_name: impl Into<String>,
-    registry: &Url,
+    source: &dyn RegistrySource,
 ) -> CargoResult<Vec<CrateVersion>> {
-    let index = crates_index::Index::from_url(registry.as_str())?;
-
     let crate_name = crate_name.into();
     let mut names = gen_fuzzy_crate_names(crate_name.clone())?;
     if let Some(index) = names.iter().position(|x| *x == crate_name) {
@@ -103,24 +355,221 @@ fn fuzzy_query_registry_index(
     }
 
     for the_name in names {
-        let crate_ = match index.crate_(&the_name) {
-            Some(crate_) => crate_,
-            None => continue,
-        };
-        return crate_
-            .versions()
-            .iter()
-            .map(|v| {
-                Ok(CrateVersion {
-                    name: v.name().to_owned(),
-                    version: v.version().parse()?,
-                    yanked: v.is_yanked(),
-                    available_features: registry_features(v),
-                })
-            })
-            .collect();
+        if let Some(versions) = source.versions(&the_name)? {
+            return Ok(versions);
+        }
+    }
+    Err(source.crate_not_found(&crate_name))
+}
+
+/// Path of the file of a crate in a registry index
+pub(crate) fn index_path(crate_name: &str) -> Option<String> {
+    if !crate_name.is_ascii() || crate_name.is_empty() {
+        return None;
+    }
+    let name = crate_name.to_ascii_lowercase();
+    let path = match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    };
+    Some(path)
+}
+
+fn source_path(location: &Url) -> CargoResult<PathBuf> {
+    location
+        .to_file_path()
+        .map_err(|()| anyhow::format_err!("Invalid source path `{}`", location))
+}
+
+/// Read the packages of a `directory` source, one per subdirectory
+fn read_packages(root: &Path) -> CargoResult<Vec<CrateVersion>> {
+    let mut packages = Vec::new();
+    let entries = std::fs::read_dir(root)
+        .with_context(|| format!("Failed to read source directory {}", root.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            packages.extend(read_package(&path.join("Cargo.toml"))?);
+        }
     }
-    Err(no_crate_err(crate_name))
+    Ok(packages)
+}
+
+/// Read the packages anywhere below `dir`, like Cargo does for git repositories
+fn read_nested_packages(dir: &Path, packages: &mut Vec<CrateVersion>) -> CargoResult<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_skipped = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_none_or(|name| name.starts_with('.') || name == "target");
+        if path.is_dir() && !is_skipped {
+            packages.extend(read_package(&path.join("Cargo.toml"))?);
+            read_nested_packages(&path, packages)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read the package of a manifest, if it's a package with a version of its own
+fn read_package(manifest_path: &Path) -> CargoResult<Option<CrateVersion>> {
+    if !manifest_path.is_file() {
+        return Ok(None);
+    }
+    let manifest = LocalManifest::try_new(manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    let package = match manifest.data.get("package") {
+        Some(package) => package,
+        None => return Ok(None),
+    };
+    let (name, version) = match (
+        package.get("name").and_then(toml_edit::Item::as_str),
+        package.get("version").and_then(toml_edit::Item::as_str),
+    ) {
+        (Some(name), Some(version)) => (name, version),
+        _ => return Ok(None),
+    };
+    Ok(Some(CrateVersion {
+        name: name.to_owned(),
+        version: version
+            .parse()
+            .map_err(|_| parse_version_err(version, name))?,
+        yanked: false,
+        available_features: manifest.features()?,
+        available_dependencies: manifest.available_dependencies(),
+        // One inherited from a workspace isn't known
+        rust_version: package
+            .get("rust-version")
+            .and_then(toml_edit::Item::as_str)
+            .and_then(|rust_version| rust_version.parse().ok()),
+    }))
+}
+
+/// Open the git index at `location`, authenticating with the token of `registry` when it has
+/// one
+///
+/// Like Cargo, crates.io is always fetched anonymously.
+fn git_index(
+    registry: &Url,
+    location: &Url,
+    manifest_path: &Path,
+    update: bool,
+) -> CargoResult<crates_index::Index> {
+    let token = match registry_credentials(manifest_path, registry)? {
+        Some(credentials)
+            if credentials.name() != CRATES_IO_REGISTRY
+                && matches!(location.scheme(), "http" | "https") =>
+        {
+            credentials.token()?
+        }
+        _ => None,
+    };
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let mut index = crates_index::Index::from_url(location.as_str())?;
+            if update {
+                let mut output = StandardStream::stderr(super::colorize_stderr());
+                while need_retry(index.update())? {
+                    registry_blocked_message(&mut output)?;
+                    std::thread::sleep(REGISTRY_BACKOFF);
+                }
+            }
+            return Ok(index);
+        }
+    };
+    let dir = cache_dir("index", location)?;
+    fetch_index(location, &token, &dir, update)
+        .with_context(|| format!("Failed to fetch registry index `{}`", location))?;
+    Ok(crates_index::Index::with_path(dir, location.as_str())?)
+}
+
+/// Fetch the git index at `url` in a bare repository, sending `token` in the `Authorization`
+/// header
+fn fetch_index(url: &Url, token: &Token, dir: &Path, update: bool) -> CargoResult<()> {
+    let repo = match git2::Repository::open_bare(dir) {
+        Ok(repo) => repo,
+        Err(_) => {
+            std::fs::create_dir_all(dir)?;
+            git2::Repository::init_bare(dir)?
+        }
+    };
+    if update || repo.refname_to_id("FETCH_HEAD").is_err() {
+        let header = format!("Authorization: {}", token.as_str());
+        let mut options = git2::FetchOptions::new();
+        options.custom_headers(&[&header]);
+        repo.remote_anonymous(url.as_str())?.fetch(
+            &[
+                "HEAD:refs/remotes/origin/HEAD",
+                "master:refs/remotes/origin/master",
+            ],
+            Some(&mut options),
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// Check out `reference` of the git repository at `url` in cargo-edit's cache, fetching the
+/// repository if it was never fetched or `update` is set
+fn git_checkout(url: &Url, reference: &GitReference, update: bool) -> CargoResult<PathBuf> {
+    let dir = cache_dir("git", url)?;
+    checkout_repository(url, reference, &dir, update)
+        .with_context(|| format!("Failed to check out git repository `{}`", url))?;
+    Ok(dir)
+}
+
+fn checkout_repository(
+    url: &Url,
+    reference: &GitReference,
+    dir: &Path,
+    update: bool,
+) -> CargoResult<()> {
+    let repo = match git2::Repository::open(dir) {
+        Ok(repo) => repo,
+        Err(_) => {
+            std::fs::create_dir_all(dir)?;
+            git2::Repository::init(dir)?
+        }
+    };
+    // `HEAD` is only set once a checkout succeeded
+    if update || repo.head().is_err() {
+        let mut remote = repo.remote_anonymous(url.as_str())?;
+        remote.fetch(
+            &[
+                "+refs/heads/*:refs/remotes/origin/*",
+                "+HEAD:refs/remotes/origin/HEAD",
+                "+refs/tags/*:refs/tags/*",
+            ],
+            None,
+            None,
+        )?;
+    }
+
+    let (spec, description) = match reference {
+        GitReference::Branch(branch) => (
+            format!("refs/remotes/origin/{}", branch),
+            format!("branch `{}`", branch),
+        ),
+        GitReference::Tag(tag) => (format!("refs/tags/{}", tag), format!("tag `{}`", tag)),
+        GitReference::Rev(rev) => (rev.clone(), format!("revision `{}`", rev)),
+        GitReference::DefaultBranch => (
+            "refs/remotes/origin/HEAD".to_owned(),
+            "default branch".to_owned(),
+        ),
+    };
+    let commit = repo
+        .revparse_single(&spec)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("Failed to find the {}", description))?;
+    repo.checkout_tree(
+        commit.as_object(),
+        Some(git2::build::CheckoutBuilder::new().force()),
+    )?;
+    repo.set_head_detached(commit.id())?;
+    Ok(())
 }
 
 /// Generate all similar crate names
@@ -167,15 +616,48 @@ fn version_is_stable(version: &CrateVersion) -> bool {
     !version.version.is_prerelease()
 }
 
+/// Whether `version` supports `rust_version`, which it does when it doesn't declare any
+fn supports_rust_version(version: &CrateVersion, rust_version: RustVersion) -> bool {
+    version
+        .rust_version
+        .is_none_or(|required| required <= rust_version)
+}
+
+/// The latest of the `versions` matching `req`
+///
+/// Yanked versions are skipped, as are pre-releases unless `flag_allow_prerelease` is set, and
+/// versions requiring a newer Rust than `rust_version`.
+pub(crate) fn latest_matching_version<'v>(
+    versions: &'v [CrateVersion],
+    req: &semver::VersionReq,
+    flag_allow_prerelease: bool,
+    rust_version: Option<RustVersion>,
+) -> Option<&'v CrateVersion> {
+    versions
+        .iter()
+        .filter(|&v| flag_allow_prerelease || version_is_stable(v))
+        .filter(|&v| !v.yanked)
+        .filter(|&v| req.matches(&v.version))
+        .filter(|&v| rust_version.is_none_or(|rust_version| supports_rust_version(v, rust_version)))
+        .max_by_key(|&v| &v.version)
+}
+
 /// Read latest version from Versions structure
+///
+/// With a `rust_version`, versions requiring a newer Rust are skipped, unless none supports it,
+/// and the newer versions skipped are reported.
 fn read_latest_version(
     versions: &[CrateVersion],
     flag_allow_prerelease: bool,
+    rust_version: Option<RustVersion>,
 ) -> CargoResult<Dependency> {
-    let latest = versions
-        .iter()
-        .filter(|&v| flag_allow_prerelease || version_is_stable(v))
-        .filter(|&v| !v.yanked)
+    let candidates = || {
+        versions
+            .iter()
+            .filter(|&v| flag_allow_prerelease || version_is_stable(v))
+            .filter(|&v| !v.yanked)
+    };
+    let latest = candidates()
         .max_by_key(|&v| v.version.clone())
         .ok_or_else(|| {
             anyhow::format_err!(
@@ -184,87 +666,131 @@ fn read_latest_version(
                          --allow-prerelease flag might solve the issue."
             )
         })?;
+    let latest = match rust_version {
+        Some(rust_version) => {
+            let compatible = candidates()
+                .filter(|&v| supports_rust_version(v, rust_version))
+                .max_by_key(|&v| v.version.clone());
+            rust_version_message(latest, compatible, rust_version)?;
+            compatible.unwrap_or(latest)
+        }
+        None => latest,
+    };
 
     let name = &latest.name;
     let version = latest.version.to_string();
     Ok(Dependency::new(name)
         .set_version(&version)
-        .set_available_features(latest.available_features.clone()))
+        .set_available_features(latest.available_features.clone())
+        .set_available_dependencies(latest.available_dependencies.clone()))
+}
+
+/// Report `latest` being passed over for `compatible`, the latest version supporting
+/// `rust_version`, or there being no such version
+fn rust_version_message(
+    latest: &CrateVersion,
+    compatible: Option<&CrateVersion>,
+    rust_version: RustVersion,
+) -> CargoResult<()> {
+    let required = match latest.rust_version {
+        Some(required) if required > rust_version => required,
+        _ => return Ok(()),
+    };
+    match compatible {
+        Some(compatible) => warning_message(format_args!(
+            "ignoring `{}@{}` which requires Rust {}, selecting `{}` for rust-version {} (use `--ignore-rust-version` to override)",
+            latest.name, latest.version, required, compatible.version, rust_version
+        )),
+        None => warning_message(format_args!(
+            "no version of `{}` supports rust-version {}, selecting `{}` which requires Rust {}",
+            latest.name, rust_version, latest.version, required
+        )),
+    }
 }
 
-/// Get crate features from registry
+/// Features of a crate and the values they activate
+type Features = BTreeMap<String, Vec<String>>;
+
+/// Get crate features from registry, along with the dependencies they can refer to
+///
+/// Nothing is returned when no version matches the requirement `version`.
 pub fn get_features_from_registry(
     crate_name: &str,
     version: &str,
     registry: &Url,
-) -> CargoResult<BTreeMap<String, Vec<String>>> {
-    if env::var("CARGO_IS_TEST").is_ok() {
-        let features = if crate_name == "your-face" {
-            [
-                ("nose".to_string(), vec![]),
-                ("mouth".to_string(), vec![]),
-                ("eyes".to_string(), vec![]),
-                ("ears".to_string(), vec![]),
-            ]
-            .into_iter()
-            .collect::<BTreeMap<_, _>>()
-        } else {
-            BTreeMap::default()
-        };
-        return Ok(features);
-    }
-
-    let index = crates_index::Index::from_url(registry.as_str())?;
+    manifest_path: &Path,
+    offline: bool,
+) -> CargoResult<(Features, BTreeMap<String, bool>)> {
     let version =
         semver::VersionReq::parse(version).map_err(|_| parse_version_err(version, crate_name))?;
 
-    let crate_ = index
-        .crate_(crate_name)
-        .ok_or_else(|| no_crate_err(crate_name))?;
-    for crate_instance in crate_.versions().iter().rev() {
-        let instance_version = match semver::Version::parse(crate_instance.version()) {
-            Ok(version) => version,
-            Err(_) => continue,
-        };
-        if version.matches(&instance_version) {
-            return Ok(registry_features(crate_instance));
-        }
-    }
-    Ok(registry_features(crate_.highest_version()))
-}
-
-fn registry_features(v: &crates_index::Version) -> BTreeMap<String, Vec<String>> {
-    let mut features: BTreeMap<_, _> = v
-        .features()
+    let source = open_registry_source(registry, manifest_path, offline)?;
+    let versions = source
+        .versions(crate_name)?
+        .ok_or_else(|| source.crate_not_found(crate_name))?;
+    // Features of other releases could differ, so none are known without a matching version
+    let crate_version = versions
         .iter()
-        .map(|(k, v)| (k.clone(), v.clone()))
-        .collect();
-    features.extend(
-        v.dependencies()
-            .iter()
-            .filter(|d| d.is_optional())
-            .map(|d| (d.crate_name().to_owned(), vec![])),
-    );
-    features
+        .filter(|v| version.matches(&v.version))
+        .max_by_key(|v| &v.version);
+    Ok(crate_version
+        .map(|crate_version| {
+            (
+                crate_version.available_features.clone(),
+                crate_version.available_dependencies.clone(),
+            )
+        })
+        .unwrap_or_default())
 }
 
 /// update registry index for given project
-pub fn update_registry_index(registry: &Url, quiet: bool) -> CargoResult<()> {
+///
+/// Git sources are fetched, while `local-registry` and `directory` sources are always up to date.
+/// Registries are authenticated with the credentials configured for `manifest_path`.
+pub fn update_registry_index(registry: &Url, manifest_path: &Path, quiet: bool) -> CargoResult<()> {
+    // A fixture registry replaces remote registries, there is nothing to fetch
+    if is_remote_registry(registry)? && FixtureRegistry::from_env().is_some() {
+        return Ok(());
+    }
+
     let colorchoice = super::colorize_stderr();
     let mut output = StandardStream::stderr(colorchoice);
 
-    let mut index = crates_index::Index::from_url(registry.as_str())?;
+    let (kind, location) = source_kind(registry)?;
+    match kind {
+        Kind::Registry | Kind::Path => {}
+        Kind::SparseRegistry => {
+            if !quiet {
+                output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+                write!(output, "{:>12}", "Updating")?;
+                output.reset()?;
+                writeln!(output, " '{}' index", registry)?;
+            }
+            // Only `config.json` is fetched upfront, the files of crates are when looking them up
+            let credentials = registry_credentials(manifest_path, registry)?;
+            SparseIndex::open(&location, credentials.as_ref())?;
+            return Ok(());
+        }
+        Kind::Git(reference) => {
+            if !quiet {
+                output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+                write!(output, "{:>12}", "Updating")?;
+                output.reset()?;
+                writeln!(output, " git repository `{}`", location)?;
+            }
+            git_checkout(&location, &reference, true)?;
+            return Ok(());
+        }
+        Kind::LocalRegistry | Kind::Directory => return Ok(()),
+    }
+
     if !quiet {
         output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
         write!(output, "{:>12}", "Updating")?;
         output.reset()?;
         writeln!(output, " '{}' index", registry)?;
     }
-
-    while need_retry(index.update())? {
-        registry_blocked_message(&mut output)?;
-        std::thread::sleep(REGISTRY_BACKOFF);
-    }
+    git_index(registry, &location, manifest_path, true)?;
 
     Ok(())
 }
@@ -375,6 +901,22 @@ where
 }
 
 fn get_cargo_toml_from_git_url(url: &str) -> CargoResult<String> {
+    let req = http_agent(url)?.get(url);
+    let res = req.call();
+    match res {
+        Ok(res) => res
+            .into_string()
+            .with_context(|| "Git response not a valid `String`"),
+        Err(err) => Err(anyhow::format_err!(
+            "HTTP request `{}` failed: {}",
+            url,
+            err
+        )),
+    }
+}
+
+/// Agent for requests to `url`, going through the proxy configured for it
+pub(crate) fn http_agent(url: &str) -> CargoResult<ureq::Agent> {
     let mut agent = ureq::AgentBuilder::new().timeout(get_default_timeout());
     #[cfg(not(any(
         target_arch = "x86_64",
@@ -394,18 +936,7 @@ fn get_cargo_toml_from_git_url(url: &str) -> CargoResult<String> {
     {
         agent = agent.proxy(proxy);
     }
-    let req = agent.build().get(url);
-    let res = req.call();
-    match res {
-        Ok(res) => res
-            .into_string()
-            .with_context(|| "Git response not a valid `String`"),
-        Err(err) => Err(anyhow::format_err!(
-            "HTTP request `{}` failed: {}",
-            url,
-            err
-        )),
-    }
+    Ok(agent.build())
 }
 
 const fn get_default_timeout() -> Duration {
@@ -442,16 +973,20 @@ fn get_latest_stable_version() {
             version: "0.6.0-alpha".parse().unwrap(),
             yanked: false,
             available_features: BTreeMap::new(),
+            available_dependencies: BTreeMap::new(),
+            rust_version: None,
         },
         CrateVersion {
             name: "foo".into(),
             version: "0.5.0".parse().unwrap(),
             yanked: false,
             available_features: BTreeMap::new(),
+            available_dependencies: BTreeMap::new(),
+            rust_version: None,
         },
     ];
     assert_eq!(
-        read_latest_version(&versions, false)
+        read_latest_version(&versions, false, None)
             .unwrap()
             .version()
             .unwrap(),
@@ -467,16 +1002,20 @@ fn get_latest_unstable_or_stable_version() {
             version: "0.6.0-alpha".parse().unwrap(),
             yanked: false,
             available_features: BTreeMap::new(),
+            available_dependencies: BTreeMap::new(),
+            rust_version: None,
         },
         CrateVersion {
             name: "foo".into(),
             version: "0.5.0".parse().unwrap(),
             yanked: false,
             available_features: BTreeMap::new(),
+            available_dependencies: BTreeMap::new(),
+            rust_version: None,
         },
     ];
     assert_eq!(
-        read_latest_version(&versions, true)
+        read_latest_version(&versions, true, None)
             .unwrap()
             .version()
             .unwrap(),
@@ -492,16 +1031,20 @@ fn get_latest_version_with_yanked() {
             version: "0.3.1".parse().unwrap(),
             yanked: true,
             available_features: BTreeMap::new(),
+            available_dependencies: BTreeMap::new(),
+            rust_version: None,
         },
         CrateVersion {
             name: "true".into(),
             version: "0.3.0".parse().unwrap(),
             yanked: false,
             available_features: BTreeMap::new(),
+            available_dependencies: BTreeMap::new(),
+            rust_version: None,
         },
     ];
     assert_eq!(
-        read_latest_version(&versions, false)
+        read_latest_version(&versions, false, None)
             .unwrap()
             .version()
             .unwrap(),
@@ -517,13 +1060,316 @@ fn get_no_latest_version_from_json_when_all_are_yanked() {
             version: "0.3.1".parse().unwrap(),
             yanked: true,
             available_features: BTreeMap::new(),
+            available_dependencies: BTreeMap::new(),
+            rust_version: None,
         },
         CrateVersion {
             name: "true".into(),
             version: "0.3.0".parse().unwrap(),
             yanked: true,
             available_features: BTreeMap::new(),
+            available_dependencies: BTreeMap::new(),
+            rust_version: None,
         },
     ];
-    assert!(read_latest_version(&versions, false).is_err());
+    assert!(read_latest_version(&versions, false, None).is_err());
+}
+
+#[test]
+fn get_latest_version_supporting_rust_version() {
+    let version = |version: &str, rust_version: Option<&str>| CrateVersion {
+        name: "foo".into(),
+        version: version.parse().unwrap(),
+        yanked: false,
+        available_features: BTreeMap::new(),
+        available_dependencies: BTreeMap::new(),
+        rust_version: rust_version.map(|rust_version| rust_version.parse().unwrap()),
+    };
+    let versions = vec![
+        version("0.1.0", None),
+        version("0.2.0", Some("1.56")),
+        version("0.3.0", Some("1.60.1")),
+        version("0.4.0", Some("1.70")),
+    ];
+    let latest = |rust_version: Option<&str>| {
+        read_latest_version(
+            &versions,
+            false,
+            rust_version.map(|rust_version| rust_version.parse().unwrap()),
+        )
+        .unwrap()
+        .version()
+        .unwrap()
+        .to_owned()
+    };
+    assert_eq!(latest(None), "0.4.0");
+    assert_eq!(latest(Some("1.70")), "0.4.0");
+    assert_eq!(latest(Some("1.60")), "0.2.0");
+    assert_eq!(latest(Some("1.60.1")), "0.3.0");
+    // Versions that don't declare a `rust-version` support any
+    assert_eq!(
+        read_latest_version(&versions[..2], false, Some("1.40".parse().unwrap()))
+            .unwrap()
+            .version()
+            .unwrap(),
+        "0.1.0"
+    );
+    // Without a supported version, the latest one is picked
+    assert_eq!(
+        read_latest_version(&versions[1..], false, Some("1.40".parse().unwrap()))
+            .unwrap()
+            .version()
+            .unwrap(),
+        "0.4.0"
+    );
+}
+
+#[test]
+fn get_latest_matching_version() {
+    let version = |version: &str, yanked: bool| CrateVersion {
+        name: "foo".into(),
+        version: version.parse().unwrap(),
+        yanked,
+        available_features: BTreeMap::new(),
+        available_dependencies: BTreeMap::new(),
+        rust_version: None,
+    };
+    let versions = vec![
+        version("1.2.0", false),
+        version("1.9.0", false),
+        version("1.10.0", true),
+        version("1.11.0-rc.1", false),
+        version("2.0.0", false),
+    ];
+    let latest = |req: &str, allow_prerelease: bool| {
+        latest_matching_version(&versions, &req.parse().unwrap(), allow_prerelease, None)
+            .map(|v| v.version.to_string())
+    };
+    assert_eq!(latest("1.2", false).unwrap(), "1.9.0");
+    assert_eq!(latest("~1.2", false).unwrap(), "1.2.0");
+    assert_eq!(latest(">=1.11.0-rc.1, <2", true).unwrap(), "1.11.0-rc.1");
+    assert_eq!(latest("2", false).unwrap(), "2.0.0");
+    assert!(latest("3", false).is_none());
+}
+
+#[test]
+fn read_local_registry() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let index = temp.path().join("index/3/f");
+    std::fs::create_dir_all(&index).unwrap();
+    let line = |version: &str, yanked: bool| {
+        format!(
+            r#"{{"name":"foo","vers":"{}","deps":[],"features":{{"std":[]}},"cksum":"{}","yanked":{}}}"#,
+            version,
+            "0".repeat(64),
+            yanked
+        )
+    };
+    std::fs::write(
+        index.join("foo"),
+        format!("{}\n{}\n", line("0.1.0", false), line("0.2.0", true)),
+    )
+    .unwrap();
+
+    let source = LocalRegistry(temp.path().to_owned());
+    let versions = source.versions("Foo").unwrap().unwrap();
+    assert_eq!(
+        read_latest_version(&versions, false, None)
+            .unwrap()
+            .version()
+            .unwrap(),
+        "0.1.0"
+    );
+    assert!(source.versions("bar").unwrap().is_none());
+}
+
+#[test]
+fn read_directory_source() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let package = |dir: &str, version: &str| {
+        let dir = temp.path().join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"foo\"\nversion = \"{}\"\n\n[dependencies]\nserde = {{ version = \"1.0\", optional = true }}\n",
+                version
+            ),
+        )
+        .unwrap();
+    };
+    package("foo", "0.2.0");
+    package("foo-0.1.0", "0.1.0");
+    std::fs::write(temp.path().join("README"), "").unwrap();
+
+    let source = Packages(read_packages(temp.path()).unwrap());
+    let versions = source.versions("foo").unwrap().unwrap();
+    assert_eq!(versions.len(), 2);
+    let latest = read_latest_version(&versions, false, None).unwrap();
+    assert_eq!(latest.version().unwrap(), "0.2.0");
+    assert_eq!(
+        latest.available_features.keys().collect::<Vec<_>>(),
+        ["serde"]
+    );
+}
+
+#[test]
+fn checkout_git_reference() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let upstream = temp.path().join("upstream");
+    let repo = git2::Repository::init(&upstream).unwrap();
+    let signature = git2::Signature::now("test", "test@example.com").unwrap();
+    let commit = |version: &str| {
+        std::fs::create_dir_all(upstream.join("nested")).unwrap();
+        std::fs::write(
+            upstream.join("nested/Cargo.toml"),
+            format!("[package]\nname = \"foo\"\nversion = \"{}\"\n", version),
+        )
+        .unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("nested/Cargo.toml")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            version,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    };
+    let first = commit("0.1.0");
+    repo.tag_lightweight("v0.1.0", &repo.find_object(first, None).unwrap(), false)
+        .unwrap();
+    commit("0.2.0");
+
+    let url = Url::from_directory_path(&upstream).unwrap();
+    let checkout = temp.path().join("checkout");
+    let version = || {
+        let mut packages = Vec::new();
+        read_nested_packages(&checkout, &mut packages).unwrap();
+        packages
+            .into_iter()
+            .map(|package| package.version.to_string())
+            .collect::<Vec<_>>()
+    };
+    checkout_repository(&url, &GitReference::DefaultBranch, &checkout, false).unwrap();
+    assert_eq!(version(), ["0.2.0"]);
+    checkout_repository(
+        &url,
+        &GitReference::Tag("v0.1.0".to_owned()),
+        &checkout,
+        false,
+    )
+    .unwrap();
+    assert_eq!(version(), ["0.1.0"]);
+    assert!(checkout_repository(
+        &url,
+        &GitReference::Branch("missing".to_owned()),
+        &checkout,
+        true
+    )
+    .is_err());
+}
+
+#[test]
+fn read_offline_cache() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let dir = "example.com-0123456789abcdef";
+    let line = r#"{"name":"foo","vers":"0.1.0","deps":[],"features":{"std":[]},"cksum":"0000000000000000000000000000000000000000000000000000000000000000"}"#;
+    let mut cache = vec![3, 2, 0, 0, 0];
+    cache.extend_from_slice(b"etag: \"1\"\0");
+    cache.extend_from_slice(b"0.1.0\0");
+    cache.extend_from_slice(line.as_bytes());
+    cache.push(0);
+    let index = temp.path().join("index").join(dir).join(".cache/3/f");
+    std::fs::create_dir_all(&index).unwrap();
+    std::fs::write(index.join("foo"), cache).unwrap();
+    let crates = temp.path().join("cache").join(dir);
+    std::fs::create_dir_all(&crates).unwrap();
+    for file in ["foo-0.1.0.crate", "foo-0.2.0.crate", "foo-bar-1.0.0.crate"] {
+        std::fs::write(crates.join(file), "").unwrap();
+    }
+    let package = temp.path().join("src").join(dir).join("foo-0.2.0");
+    std::fs::create_dir_all(&package).unwrap();
+    std::fs::write(
+        package.join("Cargo.toml"),
+        "[package]\nname = \"foo\"\nversion = \"0.2.0\"\n\n[features]\nalloc = []\n",
+    )
+    .unwrap();
+
+    let source = LocalCache {
+        registry: Url::parse("sparse+https://example.com/index/").unwrap(),
+        root: temp.path().to_owned(),
+        dirs: vec![dir.to_owned()],
+    };
+    let versions = source.versions("foo").unwrap().unwrap();
+    assert_eq!(
+        versions
+            .iter()
+            .map(|v| (
+                v.version.to_string(),
+                v.available_features.keys().cloned().collect::<Vec<_>>()
+            ))
+            .collect::<Vec<_>>(),
+        [
+            ("0.1.0".to_owned(), vec!["std".to_owned()]),
+            ("0.2.0".to_owned(), vec!["alloc".to_owned()]),
+        ]
+    );
+    assert!(source.versions("bar").unwrap().is_none());
+    assert!(source
+        .crate_not_found("bar")
+        .to_string()
+        .contains("local cache"));
+}
+
+#[test]
+fn read_default_crates_io_cache() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let write_cache = |dir: &str, version: &str| {
+        let line = format!(
+            r#"{{"name":"foo","vers":"{}","deps":[],"features":{{}},"cksum":"{}"}}"#,
+            version,
+            "0".repeat(64)
+        );
+        let mut cache = vec![3, 2, 0, 0, 0];
+        cache.extend_from_slice(b"etag: \"1\"\0");
+        cache.extend_from_slice(version.as_bytes());
+        cache.push(0);
+        cache.extend_from_slice(line.as_bytes());
+        cache.push(0);
+        let index = temp.path().join("index").join(dir).join(".cache/3/f");
+        std::fs::create_dir_all(&index).unwrap();
+        std::fs::write(index.join("foo"), cache).unwrap();
+    };
+    // Where Cargo caches crates.io with the sparse protocol, its default since Rust 1.70
+    write_cache("index.crates.io-1949cf8c6b5b557f", "0.1.0");
+    // Another index on the same host as the git index of crates.io
+    write_cache("github.com-0123456789abcdef", "0.2.0");
+
+    let crates_io = Url::parse("https://github.com/rust-lang/crates.io-index").unwrap();
+    let source = LocalCache::with_root(
+        temp.path().to_owned(),
+        &crates_io,
+        &Kind::Registry,
+        &crates_io,
+    )
+    .unwrap();
+    let versions = source.versions("foo").unwrap().unwrap();
+    assert_eq!(
+        versions
+            .iter()
+            .map(|v| v.version.to_string())
+            .collect::<Vec<_>>(),
+        ["0.1.0"]
+    );
+
+    let other = Url::parse("https://github.com/example/index").unwrap();
+    assert!(
+        LocalCache::with_root(temp.path().to_owned(), &other, &Kind::Registry, &other).is_err()
+    );
 }
@@ -0,0 +1,114 @@
+//! Hashes that don't change across Rust releases or platforms, to name directories on disk
+
+/// SipHash state, keyed with zeros
+struct SipState {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+}
+
+impl SipState {
+    fn new() -> Self {
+        SipState {
+            v0: 0x736f_6d65_7073_6575,
+            v1: 0x646f_7261_6e64_6f6d,
+            v2: 0x6c79_6765_6e65_7261,
+            v3: 0x7465_6462_7974_6573,
+        }
+    }
+
+    fn rounds(&mut self, count: usize) {
+        for _ in 0..count {
+            self.v0 = self.v0.wrapping_add(self.v1);
+            self.v1 = self.v1.rotate_left(13) ^ self.v0;
+            self.v0 = self.v0.rotate_left(32);
+            self.v2 = self.v2.wrapping_add(self.v3);
+            self.v3 = self.v3.rotate_left(16) ^ self.v2;
+            self.v0 = self.v0.wrapping_add(self.v3);
+            self.v3 = self.v3.rotate_left(21) ^ self.v0;
+            self.v2 = self.v2.wrapping_add(self.v1);
+            self.v1 = self.v1.rotate_left(17) ^ self.v2;
+            self.v2 = self.v2.rotate_left(32);
+        }
+    }
+
+    /// Compress `bytes`, including the final block with their length
+    fn compress(&mut self, bytes: &[u8], c_rounds: usize) {
+        let mut chunks = bytes.chunks_exact(8);
+        let mut last = [0; 8];
+        last[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+        last[7] = bytes.len() as u8;
+        let blocks = chunks
+            .by_ref()
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().expect("chunks of 8 bytes")))
+            .chain(Some(u64::from_le_bytes(last)));
+        for block in blocks {
+            self.v3 ^= block;
+            self.rounds(c_rounds);
+            self.v0 ^= block;
+        }
+    }
+
+    fn sum(&self) -> u64 {
+        self.v0 ^ self.v1 ^ self.v2 ^ self.v3
+    }
+}
+
+/// SipHash-1-3 of `bytes` with 128 bits of output, folded to 64 bits, like Cargo's `StableHasher`
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut state = SipState::new();
+    state.v1 ^= 0xee;
+    state.compress(bytes, 1);
+    state.v2 ^= 0xee;
+    state.rounds(3);
+    let low = state.sum();
+    state.v1 ^= 0xdd;
+    state.rounds(3);
+    let high = state.sum();
+    low.wrapping_mul(3).wrapping_add(high)
+}
+
+/// SipHash-2-4 of `bytes`, which Cargo hashed with before Rust 1.85
+pub(crate) fn legacy_hash(bytes: &[u8]) -> u64 {
+    let mut state = SipState::new();
+    state.compress(bytes, 2);
+    state.v2 ^= 0xff;
+    state.rounds(4);
+    state.sum()
+}
+
+/// `hash` as hex digits, like Cargo's `short_hash`
+pub(crate) fn short_hash(hash: u64) -> String {
+    hex::encode(hash.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(deprecated)]
+    fn legacy_hash_is_siphash_2_4() {
+        use std::hash::Hasher;
+
+        for input in [
+            &b""[..],
+            b"crates",
+            b"https://github.com/rust-lang/crates.io-index",
+        ] {
+            let mut hasher = std::hash::SipHasher::new_with_keys(0, 0);
+            hasher.write(input);
+            assert_eq!(legacy_hash(input), hasher.finish());
+        }
+    }
+
+    #[test]
+    fn stable_hash_is_stable() {
+        assert_eq!(short_hash(stable_hash(b"")), "2a4f8857e818ba1c");
+        assert_eq!(
+            short_hash(stable_hash(b"https://example.com/index")),
+            "d1d3c0ad04148c11"
+        );
+    }
+}
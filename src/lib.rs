@@ -17,12 +17,19 @@
 extern crate serde_derive;
 
 mod crate_spec;
+mod credentials;
 mod dependency;
 mod errors;
 mod fetch;
+mod hash;
 mod manifest;
 mod metadata;
 mod registry;
+mod resolve;
+mod source;
+mod sparse;
+mod transaction;
+mod unused;
 mod util;
 mod version;
 
@@ -30,11 +37,15 @@ pub use crate_spec::CrateSpec;
 pub use dependency::Dependency;
 pub use errors::*;
 pub use fetch::{
-    get_features_from_registry, get_latest_dependency, get_manifest_from_path,
-    get_manifest_from_url, update_registry_index,
+    get_features_from_registry, get_latest_dependency, get_latest_dependency_from,
+    get_manifest_from_path, get_manifest_from_url, open_registry_source, update_registry_index,
 };
-pub use manifest::{find, LocalManifest, Manifest};
-pub use metadata::{manifest_from_pkgid, workspace_members};
-pub use registry::registry_url;
-pub use util::{colorize_stderr, ColorChoice};
-pub use version::{upgrade_requirement, VersionExt};
+pub use manifest::{find, FeatureCleanup, LocalManifest, Manifest, ManifestKind, UpgradeScope};
+pub use metadata::{manifest_from_pkgid, resolve_workspace, workspace_members};
+pub use registry::{is_offline, registry_url};
+pub use resolve::{UpgradeCandidates, VersionResolver};
+pub use source::{CrateVersion, FixtureRegistry, GitIndex, RegistrySource, FIXTURE_REGISTRY_ENV};
+pub use transaction::ManifestTransaction;
+pub use unused::{find_unused_dependencies, UnusedDependency};
+pub use util::{closest_match, colorize_stderr, ColorChoice};
+pub use version::{upgrade_requirement, RustVersion, VersionExt};
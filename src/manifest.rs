@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::{self};
 use std::io::Write;
 use std::ops::{Deref, DerefMut};
@@ -10,9 +10,46 @@ use termcolor::{BufferWriter, Color, ColorSpec, WriteColor};
 
 use super::dependency::Dependency;
 use super::errors::*;
+use super::transaction::ManifestTransaction;
+use super::RustVersion;
 
 const MANIFEST_FILENAME: &str = "Cargo.toml";
 const DEP_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+const TARGET_KINDS: &[&str] = &["bin", "example", "test", "bench"];
+
+/// What a Cargo manifest describes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ManifestKind {
+    /// A package, possibly a member of a workspace
+    Package,
+    /// A workspace root without a package of its own
+    Virtual,
+    /// A workspace root that is also a package
+    PackageAndWorkspace,
+}
+
+/// Which existing requirements [`LocalManifest::upgrade`] replaces
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UpgradeScope {
+    /// Every requirement
+    All,
+    /// Requirements the new version is compatible with
+    Compatible,
+    /// Requirements the new version is incompatible with
+    Incompatible,
+}
+
+impl ManifestKind {
+    /// Whether the manifest has a `[package]`
+    pub fn is_package(self) -> bool {
+        matches!(self, Self::Package | Self::PackageAndWorkspace)
+    }
+
+    /// Whether the manifest has a `[workspace]`
+    pub fn is_workspace(self) -> bool {
+        matches!(self, Self::Virtual | Self::PackageAndWorkspace)
+    }
+}
 
 /// A Cargo manifest
 #[derive(Debug, Clone)]
@@ -22,6 +59,20 @@ pub struct Manifest {
 }
 
 impl Manifest {
+    /// Determine whether this is a package, a virtual workspace or both.
+    ///
+    /// Returns `None` for manifests with neither `[package]` (or `[project]`) nor `[workspace]`.
+    pub fn kind(&self) -> Option<ManifestKind> {
+        let is_package = self.data.contains_key("package") || self.data.contains_key("project");
+        let is_workspace = self.data.contains_key("workspace");
+        match (is_package, is_workspace) {
+            (true, false) => Some(ManifestKind::Package),
+            (false, true) => Some(ManifestKind::Virtual),
+            (true, true) => Some(ManifestKind::PackageAndWorkspace),
+            (false, false) => None,
+        }
+    }
+
     /// Get the manifest's package name
     pub fn package_name(&self) -> CargoResult<&str> {
         self.data
@@ -38,9 +89,9 @@ impl Manifest {
             input: &'a toml_edit::Item,
             path: &[String],
         ) -> CargoResult<&'a toml_edit::Item> {
-            if let Some(segment) = path.get(0) {
+            if let Some(segment) = path.first() {
                 let value = input
-                    .get(&segment)
+                    .get(segment)
                     .ok_or_else(|| non_existent_table_err(segment))?;
 
                 if value.is_table_like() {
@@ -66,7 +117,7 @@ impl Manifest {
             input: &'a mut toml_edit::Item,
             path: &[String],
         ) -> CargoResult<&'a mut toml_edit::Item> {
-            if let Some(segment) = path.get(0) {
+            if let Some(segment) = path.first() {
                 let value = input[&segment].or_insert(toml_edit::table());
 
                 if value.is_table_like() {
@@ -129,6 +180,23 @@ impl Manifest {
         sections
     }
 
+    /// Get `[workspace.dependencies]`, if the manifest declares it.
+    /// The returned items are always `Table` or `InlineTable`.
+    pub fn get_workspace_sections(&self) -> Vec<(Vec<String>, toml_edit::Item)> {
+        self.data
+            .get("workspace")
+            .and_then(|workspace| workspace.get("dependencies"))
+            .filter(|table| table.is_table_like())
+            .map(|table| {
+                (
+                    vec!["workspace".to_owned(), "dependencies".to_owned()],
+                    table.clone(),
+                )
+            })
+            .into_iter()
+            .collect()
+    }
+
     /// returns features exposed by this manifest
     pub fn features(&self) -> CargoResult<BTreeMap<String, Vec<String>>> {
         let mut features: BTreeMap<String, Vec<String>> = match self.data.as_table().get("features")
@@ -155,26 +223,43 @@ impl Manifest {
             },
         };
 
-        let sections = self.get_sections();
-        for (_, deps) in sections {
-            features.extend(
-                deps.as_table_like()
-                    .unwrap()
-                    .iter()
-                    .filter_map(|(key, dep_item)| {
-                        let table = dep_item.as_table_like()?;
-                        table
-                            .get("optional")
-                            .and_then(|o| o.as_value())
-                            .and_then(|o| o.as_bool())
-                            .unwrap_or(false)
-                            .then(|| (key.to_owned(), vec![]))
-                    }),
-            );
+        // Optional dependencies imply a feature of the same name, unless a feature refers to them
+        // with `dep:`
+        let explicit: BTreeSet<String> = features
+            .values()
+            .flatten()
+            .filter_map(|value| value.strip_prefix("dep:"))
+            .map(|name| name.to_owned())
+            .collect();
+        for (name, optional) in self.available_dependencies() {
+            if optional && !explicit.contains(&name) {
+                features.entry(name).or_default();
+            }
         }
 
         Ok(features)
     }
+
+    /// returns the dependencies features of this manifest can refer to, and whether they are
+    /// optional
+    pub fn available_dependencies(&self) -> BTreeMap<String, bool> {
+        let mut dependencies = BTreeMap::new();
+        for (section, deps) in self.get_sections() {
+            if section.last().map(String::as_str) == Some("dev-dependencies") {
+                continue;
+            }
+            for (key, dep_item) in deps.as_table_like().into_iter().flat_map(|t| t.iter()) {
+                let optional = dep_item
+                    .as_table_like()
+                    .and_then(|table| table.get("optional"))
+                    .and_then(|o| o.as_value())
+                    .and_then(|o| o.as_bool())
+                    .unwrap_or(false);
+                *dependencies.entry(key.to_owned()).or_insert(false) |= optional;
+            }
+        }
+        dependencies
+    }
 }
 
 impl str::FromStr for Manifest {
@@ -229,47 +314,94 @@ impl LocalManifest {
     /// Construct the `LocalManifest` corresponding to the `Path` provided.
     pub fn try_new(path: &Path) -> CargoResult<Self> {
         let path = path.to_path_buf();
-        let data =
-            std::fs::read_to_string(&path).with_context(|| "Failed to read manifest contents")?;
+        let data = fs::read_to_string(&path).with_context(|| "Failed to read manifest contents")?;
+        // The byte order mark is restored when writing, see `ManifestTransaction::commit`
+        let data = data.strip_prefix('\u{feff}').unwrap_or(&data);
         let manifest = data.parse().with_context(|| "Unable to parse Cargo.toml")?;
         Ok(LocalManifest { manifest, path })
     }
 
-    /// Write changes back to the file
+    /// Write changes to package-level tables back to the file
+    ///
+    /// To write several manifests all-or-nothing, stage them in a [`ManifestTransaction`].
     pub fn write(&self) -> CargoResult<()> {
-        if !self.manifest.data.contains_key("package")
-            && !self.manifest.data.contains_key("project")
-        {
-            if self.manifest.data.contains_key("workspace") {
-                anyhow::bail!(
-                    "Found virtual manifest at {}, but this command requires running against an \
-                         actual package in this workspace.",
-                    self.path.display()
-                );
-            } else {
-                anyhow::bail!(
-                    "Missing expected `package` or `project` fields in {}",
-                    self.path.display()
-                );
-            }
-        }
+        let mut transaction = ManifestTransaction::new();
+        transaction.stage(self)?;
+        transaction.commit()
+    }
+
+    /// Write changes to workspace-level tables back to the file
+    ///
+    /// Unlike [`LocalManifest::write`], this accepts virtual manifests.
+    pub fn write_workspace(&self) -> CargoResult<()> {
+        let mut transaction = ManifestTransaction::new();
+        transaction.stage_workspace(self)?;
+        transaction.commit()
+    }
 
-        let s = self.manifest.data.to_string();
-        let new_contents_bytes = s.as_bytes();
+    /// Ensure package-level edits can be written
+    pub(crate) fn check_package(&self) -> CargoResult<()> {
+        match self.manifest.kind() {
+            Some(kind) if kind.is_package() => Ok(()),
+            Some(_) => anyhow::bail!(
+                "Found virtual manifest at {}, but this command requires running against an \
+                     actual package in this workspace.",
+                self.path.display()
+            ),
+            None => anyhow::bail!(
+                "Missing expected `package` or `project` fields in {}",
+                self.path.display()
+            ),
+        }
+    }
 
-        std::fs::write(&self.path, new_contents_bytes)
-            .with_context(|| "Failed to write updated Cargo.toml")
+    /// Ensure workspace-level edits can be written
+    pub(crate) fn check_workspace(&self) -> CargoResult<()> {
+        match self.manifest.kind() {
+            Some(kind) if kind.is_workspace() => Ok(()),
+            _ => anyhow::bail!(
+                "Missing expected `workspace` table in {}",
+                self.path.display()
+            ),
+        }
     }
 
     /// Instruct this manifest to upgrade a single dependency. If this manifest does not have that
     /// dependency, it does nothing.
+    ///
+    /// Only the requirements in `scope` are replaced. The change is not written back; see
+    /// [`LocalManifest::write`].
     pub fn upgrade(
         &mut self,
         dependency: &Dependency,
         dry_run: bool,
-        skip_compatible: bool,
+        scope: UpgradeScope,
     ) -> CargoResult<()> {
-        for (table_path, table) in self.get_sections() {
+        self.upgrade_sections(self.get_sections(), dependency, dry_run, scope)
+    }
+
+    /// Instruct this manifest to upgrade a single dependency in `[workspace.dependencies]`. If
+    /// the workspace does not declare that dependency, it does nothing.
+    ///
+    /// Only the requirements in `scope` are replaced. The change is not written back; see
+    /// [`LocalManifest::write_workspace`].
+    pub fn upgrade_workspace(
+        &mut self,
+        dependency: &Dependency,
+        dry_run: bool,
+        scope: UpgradeScope,
+    ) -> CargoResult<()> {
+        self.upgrade_sections(self.get_workspace_sections(), dependency, dry_run, scope)
+    }
+
+    fn upgrade_sections(
+        &mut self,
+        sections: Vec<(Vec<String>, toml_edit::Item)>,
+        dependency: &Dependency,
+        dry_run: bool,
+        scope: UpgradeScope,
+    ) -> CargoResult<()> {
+        for (table_path, table) in sections {
             let table_like = table.as_table_like().expect("Unexpected non-table");
             for (name, toml_item) in table_like.iter() {
                 let dep_name = toml_item
@@ -277,18 +409,28 @@ impl LocalManifest {
                     .and_then(|t| t.get("package").and_then(|p| p.as_str()))
                     .unwrap_or(name);
                 if dep_name == dependency.name {
-                    if skip_compatible {
+                    if is_inherited(toml_item) {
+                        // The requirement lives in `[workspace.dependencies]`
+                        continue;
+                    }
+                    if scope != UpgradeScope::All {
                         let old_version = get_version(toml_item)?;
-                        if old_version_compatible(dependency, old_version)? {
+                        let compatible = old_version_compatible(dependency, old_version)?;
+                        if compatible != (scope == UpgradeScope::Compatible) {
                             continue;
                         }
                     }
-                    self.update_table_named_entry(&table_path, name, dependency, dry_run)?;
+                    let kind = match scope {
+                        UpgradeScope::All => None,
+                        UpgradeScope::Compatible => Some("compatible"),
+                        UpgradeScope::Incompatible => Some("incompatible"),
+                    };
+                    self.update_entry(&table_path, name, dependency, dry_run, kind)?;
                 }
             }
         }
 
-        self.write()
+        Ok(())
     }
 
     /// Lookup a dependency
@@ -306,11 +448,80 @@ impl LocalManifest {
         })
     }
 
+    /// Lookup a dependency declared in `[workspace.dependencies]`
+    pub fn get_workspace_dependency(&self, dep_key: &str) -> CargoResult<Dependency> {
+        self.get_dependency(
+            &["workspace".to_owned(), "dependencies".to_owned()],
+            dep_key,
+        )
+    }
+
+    /// Resolve a dependency inherited with `workspace = true` against the workspace root.
+    ///
+    /// Dependencies that are not inherited are returned unchanged.
+    pub fn resolve_workspace_dependency(&self, dep: Dependency) -> CargoResult<Dependency> {
+        if !dep.is_workspace() {
+            return Ok(dep);
+        }
+
+        let root_path = self
+            .workspace_root()?
+            .ok_or_else(|| no_workspace_root_err(dep.toml_key(), self.path.display()))?;
+        let workspace_dep = if root_path == self.path {
+            self.get_workspace_dependency(dep.toml_key())?
+        } else {
+            LocalManifest::try_new(&root_path)?.get_workspace_dependency(dep.toml_key())?
+        };
+        Ok(dep.inherit(&workspace_dep))
+    }
+
+    /// Locate the root manifest of the workspace this manifest belongs to.
+    ///
+    /// Like Cargo, this honors `package.workspace` and otherwise picks the closest manifest with
+    /// a `[workspace]` table, starting with this one.
+    pub fn workspace_root(&self) -> CargoResult<Option<PathBuf>> {
+        if self.data.contains_key("workspace") {
+            return Ok(Some(self.path.clone()));
+        }
+
+        let crate_root = self.path.parent().expect("manifest path is absolute");
+        if let Some(root) = self
+            .data
+            .get("package")
+            .and_then(|p| p.get("workspace"))
+            .and_then(|w| w.as_str())
+        {
+            let path = dunce::canonicalize(crate_root.join(root).join(MANIFEST_FILENAME))
+                .with_context(|| format!("Failed to find workspace root `{}`", root))?;
+            return Ok(Some(path));
+        }
+
+        for dir in crate_root.ancestors().skip(1) {
+            let path = dir.join(MANIFEST_FILENAME);
+            if !path.is_file() {
+                continue;
+            }
+            let manifest = LocalManifest::try_new(&path)?;
+            if manifest.data.contains_key("workspace") {
+                return Ok(Some(path));
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Returns all dependencies
     pub fn get_dependencies(
         &self,
     ) -> impl Iterator<Item = (Vec<String>, CargoResult<Dependency>)> + '_ {
-        self.filter_dependencies(|_| true)
+        self.filter_dependencies(self.get_sections(), |_| true)
+    }
+
+    /// Returns all dependencies declared in `[workspace.dependencies]`
+    pub fn get_workspace_dependencies(
+        &self,
+    ) -> impl Iterator<Item = (Vec<String>, CargoResult<Dependency>)> + '_ {
+        self.filter_dependencies(self.get_workspace_sections(), |_| true)
     }
 
     /// Lookup a dependency
@@ -318,18 +529,19 @@ impl LocalManifest {
         &'s self,
         dep_key: &'s str,
     ) -> impl Iterator<Item = (Vec<String>, CargoResult<Dependency>)> + 's {
-        self.filter_dependencies(move |key| key == dep_key)
+        self.filter_dependencies(self.get_sections(), move |key| key == dep_key)
     }
 
     fn filter_dependencies<'s, P>(
         &'s self,
+        sections: Vec<(Vec<String>, toml_edit::Item)>,
         mut predicate: P,
     ) -> impl Iterator<Item = (Vec<String>, CargoResult<Dependency>)> + 's
     where
         P: FnMut(&str) -> bool + 's,
     {
         let crate_root = self.path.parent().expect("manifest path is absolute");
-        self.get_sections()
+        sections
             .into_iter()
             .filter_map(move |(table_path, table)| {
                 let table = table.into_table().ok()?;
@@ -368,13 +580,22 @@ impl LocalManifest {
         &mut self,
         table_path: &[String],
         dep: &Dependency,
+    ) -> CargoResult<()> {
+        self.insert_into_table_named(table_path, dep.toml_key(), dep)
+    }
+
+    /// Add entry with a specified name to a Cargo.toml.
+    pub fn insert_into_table_named(
+        &mut self,
+        table_path: &[String],
+        dep_key: &str,
+        dep: &Dependency,
     ) -> CargoResult<()> {
         let crate_root = self
             .path
             .parent()
             .expect("manifest path is absolute")
             .to_owned();
-        let dep_key = dep.toml_key();
 
         let table = self.get_table_mut(table_path)?;
         if let Some(dep_item) = table.as_table_like_mut().unwrap().get_mut(dep_key) {
@@ -407,6 +628,18 @@ impl LocalManifest {
         dep_key: &str,
         dep: &Dependency,
         dry_run: bool,
+    ) -> CargoResult<()> {
+        self.update_entry(table_path, dep_key, dep, dry_run, None)
+    }
+
+    /// Update an entry, reporting the upgrade as being of `kind`
+    fn update_entry(
+        &mut self,
+        table_path: &[String],
+        dep_key: &str,
+        dep: &Dependency,
+        dry_run: bool,
+        kind: Option<&str>,
     ) -> CargoResult<()> {
         let crate_root = self
             .path
@@ -419,7 +652,8 @@ impl LocalManifest {
         if table.as_table_like().unwrap().contains_key(dep_key) {
             let new_dependency = dep.to_toml(&crate_root);
 
-            if let Err(e) = print_upgrade_if_necessary(&dep.name, &table[dep_key], &new_dependency)
+            if let Err(e) =
+                print_upgrade_if_necessary(&dep.name, &table[dep_key], &new_dependency, kind)
             {
                 eprintln!("Error while displaying upgrade message, {}", e);
             }
@@ -475,6 +709,44 @@ impl LocalManifest {
         Ok(())
     }
 
+    /// Remove entry from a possibly nested table, like `patch.crates-io`, and drop the tables
+    /// left empty.
+    pub fn remove_from_table_path(&mut self, table_path: &[String], name: &str) -> CargoResult<()> {
+        let table_name = table_path.join(".");
+        self.get_table(table_path)
+            .ok()
+            .and_then(toml_edit::Item::as_table_like)
+            .ok_or_else(|| non_existent_table_err(&table_name))?;
+        self.get_table_mut(table_path)?
+            .as_table_like_mut()
+            .expect("checked above")
+            .remove(name)
+            .filter(|dep| !dep.is_none())
+            .ok_or_else(|| non_existent_dependency_err(name, &table_name))?;
+
+        for depth in (1..=table_path.len()).rev() {
+            let is_empty = self
+                .get_table(&table_path[..depth])
+                .ok()
+                .and_then(toml_edit::Item::as_table_like)
+                .is_some_and(toml_edit::TableLike::is_empty);
+            if !is_empty {
+                break;
+            }
+            let (key, parent_path) = table_path[..depth].split_last().expect("depth >= 1");
+            let parent = if parent_path.is_empty() {
+                self.data.as_item_mut()
+            } else {
+                self.get_table_mut(parent_path)?
+            };
+            if let Some(parent) = parent.as_table_like_mut() {
+                parent.remove(key);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Add multiple dependencies to manifest
     pub fn add_deps(&mut self, table: &[String], deps: &[Dependency]) -> CargoResult<()> {
         deps.iter()
@@ -487,7 +759,7 @@ impl LocalManifest {
     /// Allow mutating depedencies, wherever they live
     pub fn get_dependency_tables_mut<'r>(
         &'r mut self,
-    ) -> impl Iterator<Item = &mut dyn toml_edit::TableLike> + 'r {
+    ) -> impl Iterator<Item = &'r mut dyn toml_edit::TableLike> + 'r {
         let root = self.data.as_table_mut();
         root.iter_mut().flat_map(|(k, v)| {
             if DEP_TABLES.contains(&k.get()) {
@@ -514,26 +786,276 @@ impl LocalManifest {
         })
     }
 
+    /// Allow mutating `[workspace.dependencies]`, if present
+    pub fn get_workspace_dependency_table_mut(&mut self) -> Option<&mut dyn toml_edit::TableLike> {
+        self.data
+            .get_mut("workspace")
+            .and_then(|workspace| workspace.get_mut("dependencies"))
+            .and_then(toml_edit::Item::as_table_like_mut)
+    }
+
+    /// Whether the package inherits its version with `version.workspace = true`
+    pub fn is_package_version_inherited(&self) -> bool {
+        self.data
+            .get("package")
+            .and_then(|package| package.get("version"))
+            .and_then(|version| version.get("workspace"))
+            .and_then(toml_edit::Item::as_bool)
+            .unwrap_or(false)
+    }
+
     /// Override the manifest's version
+    ///
+    /// A version inherited from the workspace is left untouched; update
+    /// [`LocalManifest::set_workspace_package_version`] on the workspace root instead.
     pub fn set_package_version(&mut self, version: &Version) {
+        if self.is_package_version_inherited() {
+            return;
+        }
         self.data["package"]["version"] = toml_edit::value(version.to_string());
     }
 
-    /// Remove references to `dep_key` if its no longer present
-    pub fn gc_dep(&mut self, dep_key: &str) {
+    /// Override the version shared through `[workspace.package]`
+    pub fn set_workspace_package_version(&mut self, version: &Version) {
+        self.data["workspace"]["package"]["version"] = toml_edit::value(version.to_string());
+    }
+
+    /// The `rust-version` of the package, read from the workspace root when inherited
+    ///
+    /// A workspace root without a package has the `rust-version` its members can inherit.
+    pub fn rust_version(&self) -> CargoResult<Option<RustVersion>> {
+        let package = self
+            .data
+            .get("package")
+            .or_else(|| self.data.get("project"));
+        let rust_version = match package {
+            Some(package) => package.get("rust-version"),
+            None => return self.workspace_rust_version(),
+        };
+        match rust_version {
+            Some(item)
+                if item.get("workspace").and_then(toml_edit::Item::as_bool) == Some(true) =>
+            {
+                let root_path = self
+                    .workspace_root()?
+                    .ok_or_else(|| inherited_rust_version_err(self.path.display()))?;
+                if root_path == self.path {
+                    self.workspace_rust_version()
+                } else {
+                    LocalManifest::try_new(&root_path)?.workspace_rust_version()
+                }
+            }
+            Some(item) => parse_rust_version(item, &self.path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The `rust-version` members of the workspace can inherit, from `[workspace.package]`
+    pub fn workspace_rust_version(&self) -> CargoResult<Option<RustVersion>> {
+        self.data
+            .get("workspace")
+            .and_then(|workspace| workspace.get("package"))
+            .and_then(|package| package.get("rust-version"))
+            .map(|item| parse_rust_version(item, &self.path))
+            .transpose()
+    }
+
+    /// Remove references to `dep_key` if its no longer present, returning what was changed
+    ///
+    /// Activations of a dependency that was removed are dropped, as are `dep:` activations of one
+    /// that is no longer optional, whose weak `dep?/feature` activations become `dep/feature`.
+    /// Features left with nothing to activate by this are removed as well.
+    pub fn gc_dep(&mut self, dep_key: &str) -> CargoResult<Vec<FeatureCleanup>> {
         let status = self.dep_feature(dep_key);
-        if matches!(status, FeatureStatus::None | FeatureStatus::DepFeature) {
-            if let toml_edit::Item::Table(feature_table) = &mut self.data.as_table_mut()["features"]
+        if !matches!(status, FeatureStatus::None | FeatureStatus::DepFeature) {
+            return Ok(Vec::new());
+        }
+        let non_empty = self.non_empty_features();
+        // A plain activation refers to a feature of the same name rather than the dependency
+        let is_feature = self
+            .data
+            .get("features")
+            .and_then(|features| features.get(dep_key))
+            .is_some_and(|item| !item.is_none());
+
+        let mut cleanups = Vec::new();
+        if let toml_edit::Item::Table(feature_table) = &mut self.data.as_table_mut()["features"] {
+            for (feature, mut activated_crates) in feature_table.iter_mut() {
+                if let toml_edit::Item::Value(toml_edit::Value::Array(feature_activations)) =
+                    &mut activated_crates
+                {
+                    let changes =
+                        remove_feature_activation(feature_activations, dep_key, status, is_feature);
+                    cleanups.extend(changes.into_iter().map(|(activation, replacement)| {
+                        let feature = feature.get().to_owned();
+                        match replacement {
+                            Some(replacement) => FeatureCleanup::Rewritten {
+                                feature,
+                                activation,
+                                replacement,
+                            },
+                            None => FeatureCleanup::Removed {
+                                feature,
+                                activation,
+                            },
+                        }
+                    }));
+                }
+            }
+        }
+
+        // Removing a feature can empty the features activating it in turn
+        loop {
+            let emptied: Vec<_> = non_empty
+                .difference(&self.non_empty_features())
+                .filter(|feature| {
+                    self.data
+                        .get("features")
+                        .and_then(|features| features.get(feature.as_str()))
+                        .is_some_and(|item| !item.is_none())
+                })
+                .cloned()
+                .collect();
+            if emptied.is_empty() {
+                break;
+            }
+            for feature in emptied {
+                let references = self.remove_feature(&feature)?;
+                cleanups.push(FeatureCleanup::FeatureRemoved {
+                    feature,
+                    references,
+                });
+            }
+        }
+
+        Ok(cleanups)
+    }
+
+    /// Names of the features activating anything
+    fn non_empty_features(&self) -> BTreeSet<String> {
+        self.data
+            .get("features")
+            .and_then(toml_edit::Item::as_table_like)
+            .into_iter()
+            .flat_map(|features| features.iter())
+            .filter(|(_, activations)| activations.as_array().is_some_and(|a| !a.is_empty()))
+            .map(|(feature, _)| feature.to_owned())
+            .collect()
+    }
+
+    /// Add `activations` to `feature`, creating the feature if needed, returning those that were
+    /// missing
+    pub fn insert_feature<'a>(
+        &mut self,
+        feature: &str,
+        activations: &[&'a str],
+    ) -> CargoResult<Vec<&'a str>> {
+        if !self.data.contains_key("features") {
+            self.data["features"] = toml_edit::table();
+        }
+        let features = self.data["features"]
+            .as_table_like_mut()
+            .ok_or_else(invalid_cargo_config)?;
+        if features.get(feature).is_none_or(toml_edit::Item::is_none) {
+            features.insert(feature, toml_edit::value(toml_edit::Array::new()));
+        }
+        let existing = features
+            .get_mut(feature)
+            .and_then(toml_edit::Item::as_array_mut)
+            .ok_or_else(invalid_cargo_config)?;
+        let mut added = Vec::new();
+        for activation in activations {
+            if !existing.iter().any(|a| a.as_str() == Some(activation)) {
+                existing.push(*activation);
+                added.push(*activation);
+            }
+        }
+        Ok(added)
+    }
+
+    /// Remove `feature` from `[features]` along with its activations by other features and the
+    /// `required-features` of targets, returning where it was referenced
+    pub fn remove_feature(&mut self, feature: &str) -> CargoResult<Vec<String>> {
+        self.data
+            .get_mut("features")
+            .and_then(toml_edit::Item::as_table_like_mut)
+            .and_then(|features| features.remove(feature))
+            .filter(|item| !item.is_none())
+            .ok_or_else(|| non_existent_feature_err(feature))?;
+        if self
+            .data
+            .get("features")
+            .and_then(toml_edit::Item::as_table_like)
+            .is_some_and(toml_edit::TableLike::is_empty)
+        {
+            self.data.remove("features");
+        }
+        // The implicit feature of an optional dependency takes over
+        if self.features()?.contains_key(feature) {
+            return Ok(Vec::new());
+        }
+
+        let mut references = Vec::new();
+        if let toml_edit::Item::Table(feature_table) = &mut self.data.as_table_mut()["features"] {
+            for (name, activations) in feature_table.iter_mut() {
+                if let toml_edit::Item::Value(toml_edit::Value::Array(activations)) = activations {
+                    if !remove_activations(activations, |activation| activation == feature)
+                        .is_empty()
+                    {
+                        references.push(format!("features.{}", name.get()));
+                    }
+                }
+            }
+        }
+        for kind in TARGET_KINDS {
+            if let Some(targets) = self
+                .data
+                .get_mut(kind)
+                .and_then(toml_edit::Item::as_array_of_tables_mut)
             {
-                for (_feature, mut activated_crates) in feature_table.iter_mut() {
-                    if let toml_edit::Item::Value(toml_edit::Value::Array(feature_activations)) =
-                        &mut activated_crates
+                for target in targets.iter_mut() {
+                    let name = target
+                        .get("name")
+                        .and_then(|name| name.as_str())
+                        .unwrap_or_default()
+                        .to_owned();
+                    if let Some(required) = target
+                        .get_mut("required-features")
+                        .and_then(toml_edit::Item::as_array_mut)
                     {
-                        remove_feature_activation(feature_activations, dep_key, status);
+                        if !remove_activations(required, |activation| activation == feature)
+                            .is_empty()
+                        {
+                            references.push(format!("required-features of {} `{}`", kind, name));
+                        }
+                        if required.is_empty() {
+                            target.remove("required-features");
+                        }
                     }
                 }
             }
         }
+
+        Ok(references)
+    }
+
+    /// Whether the optional dependency `dep_key` still exposes an implicit feature, as it isn't
+    /// activated through `dep:` by any feature
+    pub fn exposes_implicit_feature(&self, dep_key: &str) -> bool {
+        self.dep_feature(dep_key) == FeatureStatus::Feature
+    }
+
+    /// Whether a feature activates `dep_key` through `dep:`
+    fn is_dep_activated(&self, dep_key: &str) -> bool {
+        let dep_activation = format!("dep:{}", dep_key);
+        self.data
+            .get("features")
+            .and_then(toml_edit::Item::as_table_like)
+            .into_iter()
+            .flat_map(|features| features.iter())
+            .filter_map(|(_, activations)| activations.as_array())
+            .flatten()
+            .any(|activation| activation.as_str() == Some(&dep_activation))
     }
 
     fn dep_feature(&self, dep_key: &str) -> FeatureStatus {
@@ -546,7 +1068,11 @@ impl LocalManifest {
                     let optional = optional.and_then(|i| i.as_bool());
                     let optional = optional.unwrap_or(false);
                     if optional {
-                        return FeatureStatus::Feature;
+                        return if self.is_dep_activated(dep_key) {
+                            FeatureStatus::Dep
+                        } else {
+                            FeatureStatus::Feature
+                        };
                     } else {
                         status = FeatureStatus::DepFeature;
                     }
@@ -559,40 +1085,126 @@ impl LocalManifest {
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 enum FeatureStatus {
+    /// Not a dependency
     None,
+    /// A required dependency, only its features can be activated
     DepFeature,
+    /// An optional dependency, activated through `dep:` instead of an implicit feature
+    Dep,
+    /// An optional dependency with an implicit feature
     Feature,
 }
 
+/// A change to `[features]` made by [`LocalManifest::gc_dep`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FeatureCleanup {
+    /// `activation` was dropped from `feature`
+    Removed {
+        /// Feature the activation belonged to
+        feature: String,
+        /// The activation, like `dep:serde` or `serde?/std`
+        activation: String,
+    },
+    /// `activation` in `feature` was replaced
+    Rewritten {
+        /// Feature the activation belongs to
+        feature: String,
+        /// The activation as it was
+        activation: String,
+        /// What it was replaced with
+        replacement: String,
+    },
+    /// `feature` was removed as it had nothing left to activate
+    FeatureRemoved {
+        /// Name of the feature
+        feature: String,
+        /// Where it was referenced, see [`LocalManifest::remove_feature`]
+        references: Vec<String>,
+    },
+}
+
+/// Update the activations of `dep` that are no longer valid given its `status`, returning them
+/// along with their replacement, if any
+///
+/// Plain `dep` activations are kept when `is_feature`, as they activate that feature instead.
 fn remove_feature_activation(
     feature_activations: &mut toml_edit::Array,
     dep: &str,
     status: FeatureStatus,
-) {
+    is_feature: bool,
+) -> Vec<(String, Option<String>)> {
     let dep_feature: &str = &format!("{}/", dep);
+    let weak_dep_feature: &str = &format!("{}?/", dep);
+    let dep_activation: &str = &format!("dep:{}", dep);
+
+    let mut changes = Vec::new();
+    if status == FeatureStatus::DepFeature {
+        // A required dependency is always enabled, so weak activations of its features are plain
+        // ones
+        for value in feature_activations.iter_mut() {
+            let activation = match value.as_str() {
+                Some(activation) if activation.starts_with(weak_dep_feature) => {
+                    activation.to_owned()
+                }
+                _ => continue,
+            };
+            let replacement = format!("{}{}", dep_feature, &activation[weak_dep_feature.len()..]);
+            let decor = value.decor().clone();
+            *value = toml_edit::Value::from(replacement.as_str());
+            *value.decor_mut() = decor;
+            changes.push((activation, Some(replacement)));
+        }
+    }
 
+    let removed = remove_activations(feature_activations, |activation| match status {
+        FeatureStatus::None => {
+            (activation == dep && !is_feature)
+                || activation == dep_activation
+                || activation.starts_with(dep_feature)
+                || activation.starts_with(weak_dep_feature)
+        }
+        FeatureStatus::DepFeature => {
+            (activation == dep && !is_feature) || activation == dep_activation
+        }
+        FeatureStatus::Dep | FeatureStatus::Feature => false,
+    });
+    changes.extend(removed.into_iter().map(|activation| (activation, None)));
+    changes
+}
+
+/// Remove the string values of `feature_activations` matching `predicate`, returning them
+fn remove_activations(
+    feature_activations: &mut toml_edit::Array,
+    predicate: impl Fn(&str) -> bool,
+) -> Vec<String> {
     let remove_list: Vec<usize> = feature_activations
         .iter()
         .enumerate()
         .filter_map(|(idx, feature_activation)| {
-            if let toml_edit::Value::String(feature_activation) = feature_activation {
-                let activation = feature_activation.value();
-                match status {
-                    FeatureStatus::None => activation == dep || activation.starts_with(dep_feature),
-                    FeatureStatus::DepFeature => activation == dep,
-                    FeatureStatus::Feature => false,
-                }
-                .then(|| idx)
-            } else {
-                None
-            }
+            feature_activation
+                .as_str()
+                .is_some_and(&predicate)
+                .then_some(idx)
         })
         .collect();
 
+    // The first value isn't preceded by whitespace, unlike the ones taking its place
+    let first_prefix = feature_activations
+        .get(0)
+        .map(|value| value.decor().prefix().unwrap_or_default().to_owned());
     // Remove found idx in revers order so we don't invalidate the idx.
-    for idx in remove_list.iter().rev() {
-        feature_activations.remove(*idx);
+    let mut removed: Vec<String> = remove_list
+        .iter()
+        .rev()
+        .filter_map(|idx| feature_activations.remove(*idx).as_str().map(str::to_owned))
+        .collect();
+    removed.reverse();
+    if remove_list.first() == Some(&0) {
+        if let (Some(first), Some(prefix)) = (feature_activations.get_mut(0), first_prefix) {
+            first.decor_mut().set_prefix(prefix);
+        }
     }
+    removed
 }
 
 /// If a manifest is specified, return that one, otherise perform a manifest search starting from
@@ -603,7 +1215,7 @@ fn remove_feature_activation(
 pub fn find(specified: Option<&Path>) -> CargoResult<PathBuf> {
     match specified {
         Some(path)
-            if fs::metadata(&path)
+            if fs::metadata(path)
                 .with_context(|| "Failed to get cargo file metadata")?
                 .is_file() =>
         {
@@ -650,19 +1262,47 @@ fn get_version(old_dep: &toml_edit::Item) -> CargoResult<&str> {
 
 fn old_version_compatible(dependency: &Dependency, old_version: &str) -> CargoResult<bool> {
     let old_version = VersionReq::parse(old_version)
-        .with_context(|| parse_version_err(&dependency.name, old_version))?;
+        .with_context(|| parse_version_err(old_version, &dependency.name))?;
 
     let current_version = match dependency.version() {
         Some(current_version) => current_version,
         None => return Ok(false),
     };
 
+    // With preserved precision, the new version is a requirement like `1.9`
     let current_version = Version::parse(current_version)
-        .with_context(|| parse_version_err(&dependency.name, current_version))?;
+        .or_else(|_| {
+            let req = VersionReq::parse(current_version)?;
+            let comparator = req.comparators.first().ok_or_else(|| {
+                anyhow::format_err!("Empty version requirement `{}`", current_version)
+            })?;
+            anyhow::Ok(Version {
+                major: comparator.major,
+                minor: comparator.minor.unwrap_or(0),
+                patch: comparator.patch.unwrap_or(0),
+                pre: comparator.pre.clone(),
+                build: Default::default(),
+            })
+        })
+        .with_context(|| parse_version_err(current_version, &dependency.name))?;
 
     Ok(old_version.matches(&current_version))
 }
 
+/// Whether the entry is inherited from `[workspace.dependencies]`
+fn is_inherited(item: &toml_edit::Item) -> bool {
+    item.get("workspace")
+        .and_then(|w| w.as_bool())
+        .unwrap_or(false)
+}
+
+fn parse_rust_version(item: &toml_edit::Item, path: &Path) -> CargoResult<RustVersion> {
+    item.as_str()
+        .with_context(|| format!("`rust-version` of {} is not a string", path.display()))?
+        .parse()
+        .with_context(|| format!("Invalid `rust-version` in {}", path.display()))
+}
+
 pub fn str_or_1_len_table(item: &toml_edit::Item) -> bool {
     item.is_str() || item.as_table_like().map(|t| t.len() == 1).unwrap_or(false)
 }
@@ -672,6 +1312,7 @@ fn print_upgrade_if_necessary(
     crate_name: &str,
     old_dep: &toml_edit::Item,
     new_dep: &toml_edit::Item,
+    kind: Option<&str>,
 ) -> CargoResult<()> {
     let old_version = get_version(old_dep)?;
     let new_version = get_version(new_dep)?;
@@ -690,12 +1331,17 @@ fn print_upgrade_if_necessary(
     buffer
         .set_color(&ColorSpec::new())
         .with_context(|| "Failed to clear output colour")?;
-    writeln!(
+    write!(
         &mut buffer,
         "{} v{} -> v{}",
         crate_name, old_version, new_version,
     )
     .with_context(|| "Failed to write upgrade versions")?;
+    match kind {
+        Some(kind) => writeln!(&mut buffer, " ({})", kind),
+        None => writeln!(&mut buffer),
+    }
+    .with_context(|| "Failed to write upgrade versions")?;
     bufwtr
         .print(&buffer)
         .with_context(|| "Failed to print upgrade message")?;
@@ -803,6 +1449,48 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn remove_from_nested_table() {
+        let original = r#"
+[package]
+name = "simple"
+
+[patch.crates-io]
+serde = { path = "../serde" }
+
+[patch."https://github.com/example/repo"]
+foo = { path = "../foo" }
+bar = { path = "../bar" }
+"#;
+        let expected = r#"
+[package]
+name = "simple"
+
+[patch."https://github.com/example/repo"]
+bar = { path = "../bar" }
+"#;
+        let root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let mut manifest = LocalManifest {
+            path: root.join("Cargo.toml"),
+            manifest: original.parse::<Manifest>().unwrap(),
+        };
+        let crates_io = ["patch".to_owned(), "crates-io".to_owned()];
+        let repo = [
+            "patch".to_owned(),
+            "https://github.com/example/repo".to_owned(),
+        ];
+        manifest
+            .remove_from_table_path(&crates_io, "serde")
+            .unwrap();
+        manifest.remove_from_table_path(&repo, "foo").unwrap();
+        assert!(manifest.remove_from_table_path(&repo, "foo").is_err());
+        assert!(manifest
+            .remove_from_table_path(&crates_io, "serde")
+            .is_err());
+
+        assert_eq!(expected, manifest.to_string());
+    }
+
     #[test]
     fn set_package_version_overrides() {
         let original = r#"
@@ -826,18 +1514,286 @@ edition = "2015"
             path: root.join("Cargo.toml"),
             manifest: original.parse::<Manifest>().unwrap(),
         };
-        manifest.set_package_version(&semver::Version::parse("2.0.0").unwrap());
+        manifest.set_package_version(&Version::parse("2.0.0").unwrap());
         let actual = manifest.to_string();
 
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn manifest_kind() {
+        let package: Manifest = "[package]\nname = \"simple\"\n".parse().unwrap();
+        assert_eq!(package.kind(), Some(ManifestKind::Package));
+        let project: Manifest = "[project]\nname = \"simple\"\n".parse().unwrap();
+        assert_eq!(project.kind(), Some(ManifestKind::Package));
+        let virtual_manifest: Manifest = "[workspace]\nmembers = []\n".parse().unwrap();
+        assert_eq!(virtual_manifest.kind(), Some(ManifestKind::Virtual));
+        let both: Manifest = "[package]\nname = \"simple\"\n[workspace]\n"
+            .parse()
+            .unwrap();
+        assert_eq!(both.kind(), Some(ManifestKind::PackageAndWorkspace));
+        let neither: Manifest = "[dependencies]\n".parse().unwrap();
+        assert_eq!(neither.kind(), None);
+    }
+
+    #[test]
+    fn set_package_version_keeps_inherited() {
+        let original = r#"
+[package]
+name = "simple"
+version.workspace = true
+edition = "2015"
+"#;
+        let root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let mut manifest = LocalManifest {
+            path: root.join("Cargo.toml"),
+            manifest: original.parse::<Manifest>().unwrap(),
+        };
+        assert!(manifest.is_package_version_inherited());
+        manifest.set_package_version(&Version::parse("2.0.0").unwrap());
+        let actual = manifest.to_string();
+
+        assert_eq!(original, actual);
+    }
+
+    #[test]
+    fn dep_activation_hides_implicit_feature() {
+        let original = r#"
+[dependencies]
+serde = { version = "1.0", optional = true }
+serde_json = { version = "1.0", optional = true }
+
+[features]
+json = ["dep:serde_json"]
+"#;
+        let root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let manifest = LocalManifest {
+            path: root.join("Cargo.toml"),
+            manifest: original.parse::<Manifest>().unwrap(),
+        };
+        assert!(manifest.exposes_implicit_feature("serde"));
+        assert!(!manifest.exposes_implicit_feature("serde_json"));
+        assert!(!manifest.exposes_implicit_feature("regex"));
+    }
+
+    #[test]
+    fn remove_feature_keeps_implicit_features() {
+        let original = r#"
+[dependencies]
+serde = { version = "1.0", optional = true }
+
+[features]
+serde = ["dep:serde"]
+json = ["serde", "other"]
+other = []
+"#;
+        let root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let mut manifest = LocalManifest {
+            path: root.join("Cargo.toml"),
+            manifest: original.parse::<Manifest>().unwrap(),
+        };
+        // `serde` becomes the implicit feature of the optional dependency again
+        assert_eq!(
+            manifest.remove_feature("serde").unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            manifest.remove_feature("other").unwrap(),
+            vec!["features.json".to_owned()]
+        );
+        assert!(manifest.remove_feature("other").is_err());
+        let expected = r#"
+[dependencies]
+serde = { version = "1.0", optional = true }
+
+[features]
+json = ["serde"]
+"#;
+        assert_eq!(manifest.to_string(), expected);
+    }
+
+    #[test]
+    fn gc_dep_removes_emptied_features() {
+        let original = r#"
+[dependencies]
+toml = "0.5"
+
+[features]
+serde = ["dep:serde", "serde?/derive"]
+std = ["serde?/std", "toml/preserve_order"]
+json = ["serde"]
+default = []
+
+[[bin]]
+name = "cli"
+required-features = ["json"]
+"#;
+        let root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let mut manifest = LocalManifest {
+            path: root.join("Cargo.toml"),
+            manifest: original.parse::<Manifest>().unwrap(),
+        };
+        let cleanups = manifest.gc_dep("serde").unwrap();
+        assert_eq!(
+            cleanups,
+            vec![
+                FeatureCleanup::Removed {
+                    feature: "serde".to_owned(),
+                    activation: "dep:serde".to_owned(),
+                },
+                FeatureCleanup::Removed {
+                    feature: "serde".to_owned(),
+                    activation: "serde?/derive".to_owned(),
+                },
+                FeatureCleanup::Removed {
+                    feature: "std".to_owned(),
+                    activation: "serde?/std".to_owned(),
+                },
+                FeatureCleanup::FeatureRemoved {
+                    feature: "serde".to_owned(),
+                    references: vec!["features.json".to_owned()],
+                },
+                FeatureCleanup::FeatureRemoved {
+                    feature: "json".to_owned(),
+                    references: vec!["required-features of bin `cli`".to_owned()],
+                },
+            ]
+        );
+        let expected = r#"
+[dependencies]
+toml = "0.5"
+
+[features]
+std = ["toml/preserve_order"]
+default = []
+
+[[bin]]
+name = "cli"
+"#;
+        assert_eq!(manifest.to_string(), expected);
+    }
+
+    #[test]
+    fn gc_dep_rewrites_weak_activations() {
+        let original = r#"
+[dependencies]
+serde = "1.0"
+
+[features]
+derive = ["dep:serde", "serde?/derive"]
+std = ["serde?/std"]
+"#;
+        let root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let mut manifest = LocalManifest {
+            path: root.join("Cargo.toml"),
+            manifest: original.parse::<Manifest>().unwrap(),
+        };
+        let cleanups = manifest.gc_dep("serde").unwrap();
+        assert_eq!(
+            cleanups,
+            vec![
+                FeatureCleanup::Rewritten {
+                    feature: "derive".to_owned(),
+                    activation: "serde?/derive".to_owned(),
+                    replacement: "serde/derive".to_owned(),
+                },
+                FeatureCleanup::Removed {
+                    feature: "derive".to_owned(),
+                    activation: "dep:serde".to_owned(),
+                },
+                FeatureCleanup::Rewritten {
+                    feature: "std".to_owned(),
+                    activation: "serde?/std".to_owned(),
+                    replacement: "serde/std".to_owned(),
+                },
+            ]
+        );
+        let expected = r#"
+[dependencies]
+serde = "1.0"
+
+[features]
+derive = ["serde/derive"]
+std = ["serde/std"]
+"#;
+        assert_eq!(manifest.to_string(), expected);
+    }
+
+    #[test]
+    fn set_workspace_package_version_overrides() {
+        let original = r#"
+[workspace]
+members = ["simple"]
+
+[workspace.package]
+version = "0.1.0"
+"#;
+        let expected = r#"
+[workspace]
+members = ["simple"]
+
+[workspace.package]
+version = "2.0.0"
+"#;
+        let root = dunce::canonicalize(Path::new("/")).expect("root exists");
+        let mut manifest = LocalManifest {
+            path: root.join("Cargo.toml"),
+            manifest: original.parse::<Manifest>().unwrap(),
+        };
+        manifest.set_workspace_package_version(&Version::parse("2.0.0").unwrap());
+        let actual = manifest.to_string();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rust_version_is_inherited() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = dunce::canonicalize(temp.path()).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"member\"]\n\n[workspace.package]\nrust-version = \"1.60\"\n",
+        )
+        .unwrap();
+        fs::create_dir(root.join("member")).unwrap();
+        fs::write(
+            root.join("member/Cargo.toml"),
+            "[package]\nname = \"member\"\nversion = \"0.1.0\"\nrust-version.workspace = true\n",
+        )
+        .unwrap();
+
+        let rust_version = |path: &Path| {
+            LocalManifest::try_new(&root.join(path))
+                .unwrap()
+                .rust_version()
+                .unwrap()
+                .map(|rust_version| rust_version.to_string())
+        };
+        assert_eq!(rust_version(Path::new("Cargo.toml")).unwrap(), "1.60");
+        assert_eq!(
+            rust_version(Path::new("member/Cargo.toml")).unwrap(),
+            "1.60"
+        );
+
+        let manifest = LocalManifest {
+            path: root.join("Cargo.toml"),
+            manifest: "[package]\nname = \"single\"\nversion = \"0.1.0\"\n"
+                .parse::<Manifest>()
+                .unwrap(),
+        };
+        assert!(manifest.rust_version().unwrap().is_none());
+    }
+
     #[test]
     fn old_version_is_compatible() -> CargoResult<()> {
         let with_version = Dependency::new("foo").set_version("2.3.4");
         assert!(!old_version_compatible(&with_version, "1")?);
         assert!(old_version_compatible(&with_version, "2")?);
         assert!(!old_version_compatible(&with_version, "3")?);
+
+        let with_precision = Dependency::new("foo").set_version("1.9");
+        assert!(old_version_compatible(&with_precision, "1.2")?);
+        assert!(!old_version_compatible(&with_precision, "0.9")?);
         Ok(())
     }
 
@@ -47,6 +47,18 @@ pub fn workspace_members(manifest_path: Option<&Path>) -> CargoResult<Vec<Packag
     Ok(workspace_members)
 }
 
+/// Resolve the dependencies of the workspace, with every feature enabled so optional
+/// dependencies are part of the graph
+pub fn resolve_workspace(manifest_path: Option<&Path>) -> CargoResult<cargo_metadata::Metadata> {
+    let mut cmd = cargo_metadata::MetadataCommand::new();
+    cmd.features(cargo_metadata::CargoOpt::AllFeatures);
+    if let Some(manifest_path) = manifest_path {
+        cmd.manifest_path(manifest_path);
+    }
+    cmd.exec()
+        .with_context(|| "Failed to resolve the dependencies of the workspace")
+}
+
 fn canonicalize_path(
     path: cargo_metadata::camino::Utf8PathBuf,
 ) -> cargo_metadata::camino::Utf8PathBuf {
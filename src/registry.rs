@@ -1,73 +1,40 @@
 use super::errors::*;
+use super::hash::{legacy_hash, short_hash, stable_hash};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use url::Url;
 
 const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
-const CRATES_IO_REGISTRY: &str = "crates-io";
+const CRATES_IO_SPARSE_INDEX: &str = "sparse+https://index.crates.io/";
+pub(crate) const CRATES_IO_REGISTRY: &str = "crates-io";
 
 /// Find the URL of a registry
+///
+/// Source replacement is followed, see
+/// <https://doc.rust-lang.org/cargo/reference/source-replacement.html>. Like Cargo's source ids,
+/// the URLs of sources that aren't registries are prefixed with their kind, for example
+/// `directory+file:///path/to/vendor` or `git+https://github.com/org/repo?branch=main`, while
+/// registries using the sparse protocol keep their `sparse+` prefix.
 pub fn registry_url(manifest_path: &Path, registry: Option<&str>) -> CargoResult<Url> {
-    // TODO support local registry sources, directory sources, git sources: https://doc.rust-lang.org/cargo/reference/source-replacement.html?highlight=replace-with#source-replacement
-    fn read_config(
-        registries: &mut HashMap<String, Source>,
-        path: impl AsRef<Path>,
-    ) -> CargoResult<()> {
-        // TODO unit test for source replacement
-        let content = std::fs::read(path)?;
-        let config = toml_edit::easy::from_slice::<CargoConfig>(&content)
-            .map_err(|_| invalid_cargo_config())?;
-        for (key, value) in config.registries {
-            registries.entry(key).or_insert(Source {
-                registry: value.index,
-                replace_with: None,
-            });
-        }
-        for (key, value) in config.source {
-            registries.entry(key).or_insert(value);
-        }
-        Ok(())
-    }
     // registry might be replaced with another source
     // it's looks like a singly linked list
     // put relations in this map.
-    let mut registries: HashMap<String, Source> = HashMap::new();
-    // ref: https://doc.rust-lang.org/cargo/reference/config.html#hierarchical-structure
-    for work_dir in manifest_path
-        .parent()
-        .expect("there must be a parent directory")
-        .ancestors()
-    {
-        let work_cargo_dir = work_dir.join(".cargo");
-        let config_path = work_cargo_dir.join("config");
-        if config_path.is_file() {
-            read_config(&mut registries, config_path)?;
-        } else {
-            let config_path = work_cargo_dir.join("config.toml");
-            if config_path.is_file() {
-                read_config(&mut registries, config_path)?;
-            }
-        }
-    }
-
-    let default_cargo_home = cargo_home()?;
-    let default_config_path = default_cargo_home.join("config");
-    if default_config_path.is_file() {
-        read_config(&mut registries, default_config_path)?;
-    } else {
-        let default_config_path = default_cargo_home.join("config.toml");
-        if default_config_path.is_file() {
-            read_config(&mut registries, default_config_path)?;
-        }
-    }
+    let mut registries = load_config(manifest_path)?.sources;
 
     // find head of the relevant linked list
     let mut source = match registry {
         Some(CRATES_IO_INDEX) | None => {
             let mut source = registries.remove(CRATES_IO_REGISTRY).unwrap_or_default();
-            source
-                .registry
-                .get_or_insert_with(|| CRATES_IO_INDEX.to_string());
+            let protocol = std::env::var("CARGO_REGISTRIES_CRATES_IO_PROTOCOL")
+                .ok()
+                .or_else(|| source.protocol.clone());
+            source.registry.get_or_insert_with(|| {
+                if protocol.as_deref() == Some("sparse") {
+                    CRATES_IO_SPARSE_INDEX.to_string()
+                } else {
+                    CRATES_IO_INDEX.to_string()
+                }
+            });
             source
         }
         Some(r) => registries
@@ -88,12 +55,168 @@ pub fn registry_url(manifest_path: &Path, registry: Option<&str>) -> CargoResult
         }
     }
 
-    let registry_url = source
-        .registry
-        .and_then(|x| Url::parse(&x).ok())
-        .with_context(invalid_cargo_config)?;
+    source.url()
+}
 
-    Ok(registry_url)
+/// Split a URL returned by [`registry_url`] into the kind of source and its location
+pub(crate) fn source_kind(url: &Url) -> CargoResult<(Kind, Url)> {
+    let (kind, location) = match url.scheme().split_once('+') {
+        Some((kind, _)) => (kind, &url.as_str()[kind.len() + 1..]),
+        None => return Ok((Kind::Registry, url.clone())),
+    };
+    let mut location = Url::parse(location).with_context(invalid_cargo_config)?;
+    let kind = match kind {
+        "registry" => Kind::Registry,
+        "sparse" => Kind::SparseRegistry,
+        "local-registry" => Kind::LocalRegistry,
+        "directory" => Kind::Directory,
+        "git" => {
+            let reference = location
+                .query_pairs()
+                .find_map(|(key, value)| match key.as_ref() {
+                    "branch" => Some(GitReference::Branch(value.into_owned())),
+                    "tag" => Some(GitReference::Tag(value.into_owned())),
+                    "rev" => Some(GitReference::Rev(value.into_owned())),
+                    _ => None,
+                })
+                .unwrap_or(GitReference::DefaultBranch);
+            location.set_query(None);
+            Kind::Git(reference)
+        }
+        _ => return Ok((Kind::Registry, url.clone())),
+    };
+    Ok((kind, location))
+}
+
+/// Registry settings merged from every config file applying to a manifest
+#[derive(Default, Debug)]
+pub(crate) struct Config {
+    /// Registries and sources by name
+    sources: HashMap<String, Source>,
+    /// The `[registry]` table
+    pub(crate) registry: RegistryConfig,
+    /// The `[credential-alias]` table
+    pub(crate) credential_aliases: HashMap<String, CredentialProvider>,
+    /// `net.offline`
+    offline: Option<bool>,
+}
+
+impl Config {
+    /// Name of the registry whose index is at `url`, as returned by [`registry_url`]
+    pub(crate) fn registry_name(&self, url: &Url) -> Option<&str> {
+        let normalize = |url: &str| url.trim_end_matches('/').to_owned();
+        let url = normalize(url.as_str());
+        if url == normalize(CRATES_IO_INDEX) || url == normalize(CRATES_IO_SPARSE_INDEX) {
+            return Some(CRATES_IO_REGISTRY);
+        }
+        self.sources
+            .iter()
+            .filter(|(_, source)| source.index.is_some())
+            .find(|(_, source)| source.registry.as_deref().map(normalize) == Some(url.clone()))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// `token` set for the registry `name`
+    pub(crate) fn token(&self, name: &str) -> Option<&str> {
+        if name == CRATES_IO_REGISTRY {
+            if let Some(token) = &self.registry.token {
+                return Some(token);
+            }
+        }
+        self.sources.get(name)?.token.as_deref()
+    }
+
+    /// `credential-provider` set for the registry `name`
+    pub(crate) fn credential_provider(&self, name: &str) -> Option<&CredentialProvider> {
+        if name == CRATES_IO_REGISTRY {
+            if let Some(provider) = &self.registry.credential_provider {
+                return Some(provider);
+            }
+        }
+        self.sources.get(name)?.credential_provider.as_ref()
+    }
+}
+
+/// Whether Cargo is configured to run without accessing the network, with `CARGO_NET_OFFLINE`
+/// or `net.offline`
+pub fn is_offline(manifest_path: &Path) -> CargoResult<bool> {
+    if let Ok(offline) = std::env::var("CARGO_NET_OFFLINE") {
+        return Ok(offline == "true" || offline == "1");
+    }
+    Ok(load_config(manifest_path)?.offline.unwrap_or(false))
+}
+
+/// Read the config files applying to `manifest_path`, along with the credentials stored in
+/// `$CARGO_HOME`
+///
+/// See <https://doc.rust-lang.org/cargo/reference/config.html#hierarchical-structure>.
+pub(crate) fn load_config(manifest_path: &Path) -> CargoResult<Config> {
+    fn read_first(config: &mut Config, dir: &Path, names: &[&str]) -> CargoResult<()> {
+        if let Some(path) = names
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+        {
+            read_config_file(config, path)?;
+        }
+        Ok(())
+    }
+
+    let mut config = Config::default();
+    let cargo_home = cargo_home()?;
+    // Credentials take precedence over the tokens found in config files
+    read_first(
+        &mut config,
+        &cargo_home,
+        &["credentials", "credentials.toml"],
+    )?;
+    for work_dir in manifest_path
+        .parent()
+        .expect("there must be a parent directory")
+        .ancestors()
+    {
+        read_first(
+            &mut config,
+            &work_dir.join(".cargo"),
+            &["config", "config.toml"],
+        )?;
+    }
+    read_first(&mut config, &cargo_home, &["config", "config.toml"])?;
+    Ok(config)
+}
+
+/// Merge the config file at `path` into `config`, settings already there taking precedence
+pub(crate) fn read_config_file(config: &mut Config, path: impl AsRef<Path>) -> CargoResult<()> {
+    let path = path.as_ref();
+    let content = std::fs::read(path)?;
+    let file =
+        toml_edit::easy::from_slice::<CargoConfig>(&content).map_err(|_| invalid_cargo_config())?;
+    // Paths are relative to the directory containing `.cargo/`
+    let root = path
+        .parent()
+        .and_then(Path::parent)
+        .unwrap_or_else(|| Path::new(""));
+    for (key, value) in file.registries {
+        config.sources.entry(key).or_default().merge(Source {
+            registry: value.index.clone(),
+            index: value.index,
+            protocol: value.protocol,
+            token: value.token,
+            credential_provider: value.credential_provider,
+            ..Default::default()
+        });
+    }
+    for (key, mut value) in file.source {
+        value.local_registry = value.local_registry.map(|path| root.join(path));
+        value.directory = value.directory.map(|path| root.join(path));
+        config.sources.entry(key).or_default().merge(value);
+    }
+    config.registry.merge(file.registry);
+    config.offline = config.offline.or(file.net.offline);
+    for (key, value) in file.credential_alias {
+        config.credential_aliases.entry(key).or_insert(value);
+    }
+    Ok(())
 }
 
 #[derive(Debug, Deserialize)]
@@ -102,6 +225,67 @@ struct CargoConfig {
     registries: HashMap<String, Registry>,
     #[serde(default)]
     source: HashMap<String, Source>,
+    #[serde(default)]
+    registry: RegistryConfig,
+    #[serde(rename = "credential-alias", default)]
+    credential_alias: HashMap<String, CredentialProvider>,
+    #[serde(default)]
+    net: NetConfig,
+}
+
+#[derive(Default, Debug, Deserialize)]
+struct NetConfig {
+    offline: Option<bool>,
+}
+
+/// The `[registry]` table, configuring crates.io and authentication
+#[derive(Default, Debug, Deserialize)]
+pub(crate) struct RegistryConfig {
+    /// Token for crates.io
+    pub(crate) token: Option<String>,
+    /// Provider of the token for crates.io
+    #[serde(rename = "credential-provider")]
+    pub(crate) credential_provider: Option<CredentialProvider>,
+    /// Providers of tokens for registries without a provider of their own, the last taking
+    /// precedence
+    #[serde(rename = "global-credential-providers")]
+    pub(crate) global_credential_providers: Option<Vec<CredentialProvider>>,
+}
+
+impl RegistryConfig {
+    fn merge(&mut self, other: RegistryConfig) {
+        self.token = self.token.take().or(other.token);
+        self.credential_provider = self
+            .credential_provider
+            .take()
+            .or(other.credential_provider);
+        self.global_credential_providers = self
+            .global_credential_providers
+            .take()
+            .or(other.global_credential_providers);
+    }
+}
+
+/// A credential provider, either a command line to split on whitespace or its arguments
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum CredentialProvider {
+    /// A command line, like `cargo:token`
+    String(String),
+    /// The program and its arguments
+    Args(Vec<String>),
+}
+
+impl CredentialProvider {
+    /// The program and its arguments
+    pub(crate) fn args(&self) -> Vec<String> {
+        match self {
+            CredentialProvider::String(line) => {
+                line.split_whitespace().map(str::to_owned).collect()
+            }
+            CredentialProvider::Args(args) => args.clone(),
+        }
+    }
 }
 
 #[derive(Default, Debug, Deserialize)]
@@ -109,14 +293,93 @@ struct Source {
     #[serde(rename = "replace-with")]
     replace_with: Option<String>,
     registry: Option<String>,
+    #[serde(rename = "local-registry")]
+    local_registry: Option<PathBuf>,
+    directory: Option<PathBuf>,
+    git: Option<String>,
+    branch: Option<String>,
+    tag: Option<String>,
+    rev: Option<String>,
+    /// `index` of a `[registries]` entry
+    #[serde(skip)]
+    index: Option<String>,
+    /// `protocol` of `[registries.crates-io]`
+    #[serde(skip)]
+    protocol: Option<String>,
+    /// `token` of a `[registries]` entry
+    #[serde(skip)]
+    token: Option<String>,
+    /// `credential-provider` of a `[registries]` entry
+    #[serde(skip)]
+    credential_provider: Option<CredentialProvider>,
+}
+
+impl Source {
+    /// Fill in the fields missing from `self`, as configuration read earlier takes precedence
+    fn merge(&mut self, other: Source) {
+        self.replace_with = self.replace_with.take().or(other.replace_with);
+        self.registry = self.registry.take().or(other.registry);
+        self.local_registry = self.local_registry.take().or(other.local_registry);
+        self.directory = self.directory.take().or(other.directory);
+        self.git = self.git.take().or(other.git);
+        self.branch = self.branch.take().or(other.branch);
+        self.tag = self.tag.take().or(other.tag);
+        self.rev = self.rev.take().or(other.rev);
+        self.index = self.index.take().or(other.index);
+        self.protocol = self.protocol.take().or(other.protocol);
+        self.token = self.token.take().or(other.token);
+        self.credential_provider = self
+            .credential_provider
+            .take()
+            .or(other.credential_provider);
+    }
+
+    fn url(self) -> CargoResult<Url> {
+        if let Some(registry) = self.registry {
+            return Url::parse(&registry).with_context(invalid_cargo_config);
+        }
+        if let Some(path) = self.local_registry {
+            return prefixed_path_url("local-registry", &path);
+        }
+        if let Some(path) = self.directory {
+            return prefixed_path_url("directory", &path);
+        }
+        if let Some(git) = self.git {
+            let mut url = Url::parse(&git).with_context(invalid_cargo_config)?;
+            if let Some(branch) = self.branch {
+                url.query_pairs_mut().append_pair("branch", &branch);
+            } else if let Some(tag) = self.tag {
+                url.query_pairs_mut().append_pair("tag", &tag);
+            } else if let Some(rev) = self.rev {
+                url.query_pairs_mut().append_pair("rev", &rev);
+            }
+            return Url::parse(&format!("git+{}", url)).with_context(invalid_cargo_config);
+        }
+        Err(invalid_cargo_config())
+    }
+}
+
+fn prefixed_path_url(kind: &str, path: &Path) -> CargoResult<Url> {
+    let path = if path.is_absolute() {
+        path.to_owned()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    let url = Url::from_directory_path(&path)
+        .map_err(|()| anyhow::format_err!("Invalid source path `{}`", path.display()))?;
+    Url::parse(&format!("{}+{}", kind, url)).with_context(invalid_cargo_config)
 }
 
 #[derive(Debug, Deserialize)]
 struct Registry {
     index: Option<String>,
+    protocol: Option<String>,
+    token: Option<String>,
+    #[serde(rename = "credential-provider")]
+    credential_provider: Option<CredentialProvider>,
 }
 
-fn cargo_home() -> CargoResult<PathBuf> {
+pub(crate) fn cargo_home() -> CargoResult<PathBuf> {
     let default_cargo_home = dirs_next::home_dir()
         .map(|x| x.join(".cargo"))
         .with_context(|| anyhow::format_err!("Failed to read home directory"))?;
@@ -126,6 +389,71 @@ fn cargo_home() -> CargoResult<PathBuf> {
     Ok(cargo_home)
 }
 
+/// Directory in which cargo-edit caches what it fetched from the source at `url`
+pub(crate) fn cache_dir(kind: &str, url: &Url) -> CargoResult<PathBuf> {
+    let name = url
+        .path_segments()
+        .and_then(|mut segments| segments.rfind(|segment| !segment.is_empty()))
+        .or_else(|| url.host_str())
+        .map(|name| name.trim_end_matches(".git"))
+        .filter(|name| !name.is_empty())
+        .unwrap_or("source");
+    Ok(cargo_home()?.join("cargo-edit").join(kind).join(format!(
+        "{}-{}",
+        name,
+        short_hash(stable_hash(url.as_str().as_bytes()))
+    )))
+}
+
+/// Names of the directories Cargo keeps a `Registry` or `SparseRegistry` at `location` in, in
+/// `$CARGO_HOME/registry/index`, `cache` and `src`
+///
+/// Cargo names them after the host of the index and a hash of its URL. The hash changed in
+/// Rust 1.85, so the names from both are returned. crates.io may be cached under either protocol.
+pub(crate) fn cargo_registry_dirs(kind: &Kind, location: &Url) -> Vec<String> {
+    let source = match kind {
+        Kind::SparseRegistry => format!("sparse+{}", location),
+        _ => location.to_string(),
+    };
+    let normalize = |url: &str| url.trim_end_matches('/').to_owned();
+    let sources = if [CRATES_IO_INDEX, CRATES_IO_SPARSE_INDEX]
+        .iter()
+        .any(|index| normalize(index) == normalize(&source))
+    {
+        vec![
+            (Kind::Registry, CRATES_IO_INDEX.to_owned()),
+            (Kind::SparseRegistry, CRATES_IO_SPARSE_INDEX.to_owned()),
+        ]
+    } else {
+        vec![(kind.clone(), source)]
+    };
+
+    let mut dirs = Vec::new();
+    for (kind, source) in sources {
+        // Discriminant of `SourceKind` in Cargo
+        let discriminant: u8 = match kind {
+            Kind::SparseRegistry => 3,
+            _ => 2,
+        };
+        let host = Url::parse(&source)
+            .ok()
+            .and_then(|url| url.host_str().map(ToOwned::to_owned))
+            .unwrap_or_default();
+        let mut stable = vec![discriminant];
+        stable.extend_from_slice(source.as_bytes());
+        stable.push(0xff);
+        let mut legacy = u64::from(discriminant).to_le_bytes().to_vec();
+        legacy.extend_from_slice(source.as_bytes());
+        legacy.push(0xff);
+        for hash in [stable_hash(&stable), legacy_hash(&legacy)] {
+            dirs.push(format!("{}-{}", host, short_hash(hash)));
+        }
+    }
+    dirs
+}
+
+pub(crate) use code_from_cargo::{GitReference, Kind};
+
 mod code_from_cargo {
     #![allow(dead_code)]
 
@@ -134,6 +462,7 @@ mod code_from_cargo {
         Git(GitReference),
         Path,
         Registry,
+        SparseRegistry,
         LocalRegistry,
         Directory,
     }
@@ -146,3 +475,134 @@ mod code_from_cargo {
         DefaultBranch,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_cargo_registry_dirs() {
+        let crates_io = Url::parse(CRATES_IO_INDEX).unwrap();
+        assert_eq!(
+            cargo_registry_dirs(&Kind::Registry, &crates_io),
+            [
+                "github.com-25cdd57fae9f0462",
+                "github.com-1ecc6299db9ec823",
+                "index.crates.io-1949cf8c6b5b557f",
+                "index.crates.io-6f17d22bba15001f",
+            ]
+        );
+        let sparse = Url::parse("https://index.crates.io/").unwrap();
+        assert_eq!(
+            cargo_registry_dirs(&Kind::SparseRegistry, &sparse),
+            cargo_registry_dirs(&Kind::Registry, &crates_io)
+        );
+        let other = Url::parse("https://example.com/index").unwrap();
+        assert_eq!(
+            cargo_registry_dirs(&Kind::Registry, &other)[0],
+            "example.com-3bab6764c353b068"
+        );
+    }
+
+    #[test]
+    fn follow_source_replacement() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = dunce::canonicalize(temp.path()).unwrap();
+        std::fs::create_dir_all(root.join(".cargo")).unwrap();
+        std::fs::write(
+            root.join(".cargo/config.toml"),
+            r#"
+[source.crates-io]
+replace-with = "vendored-sources"
+
+[source.vendored-sources]
+directory = "vendor"
+
+[source.mirror]
+local-registry = "registry"
+
+[source.upstream]
+git = "https://github.com/org/crates"
+branch = "main"
+
+[registries.internal]
+index = "https://example.com/index"
+
+[registries.private]
+index = "sparse+https://example.com/private/"
+"#,
+        )
+        .unwrap();
+        let manifest_path = root.join("Cargo.toml");
+
+        let url = registry_url(&manifest_path, None).unwrap();
+        assert_eq!(
+            source_kind(&url).unwrap(),
+            (
+                Kind::Directory,
+                Url::from_directory_path(root.join("vendor")).unwrap()
+            )
+        );
+        assert_eq!(
+            source_kind(&registry_url(&manifest_path, Some("mirror")).unwrap()).unwrap(),
+            (
+                Kind::LocalRegistry,
+                Url::from_directory_path(root.join("registry")).unwrap()
+            )
+        );
+        let url = registry_url(&manifest_path, Some("upstream")).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "git+https://github.com/org/crates?branch=main"
+        );
+        assert_eq!(
+            source_kind(&url).unwrap(),
+            (
+                Kind::Git(GitReference::Branch("main".to_owned())),
+                Url::parse("https://github.com/org/crates").unwrap()
+            )
+        );
+        let url = registry_url(&manifest_path, Some("internal")).unwrap();
+        assert_eq!(
+            source_kind(&url).unwrap(),
+            (
+                Kind::Registry,
+                Url::parse("https://example.com/index").unwrap()
+            )
+        );
+        let url = registry_url(&manifest_path, Some("private")).unwrap();
+        assert_eq!(
+            source_kind(&url).unwrap(),
+            (
+                Kind::SparseRegistry,
+                Url::parse("https://example.com/private/").unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn crates_io_protocol() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = dunce::canonicalize(temp.path()).unwrap();
+        std::fs::create_dir_all(root.join(".cargo")).unwrap();
+        std::fs::write(
+            root.join(".cargo/config.toml"),
+            "[source.crates-io]\n\n[registries.crates-io]\nprotocol = \"sparse\"\n",
+        )
+        .unwrap();
+        let url = registry_url(&root.join("Cargo.toml"), None).unwrap();
+        assert_eq!(url.as_str(), CRATES_IO_SPARSE_INDEX);
+    }
+
+    #[test]
+    fn net_offline() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = dunce::canonicalize(temp.path()).unwrap();
+        std::fs::create_dir_all(root.join(".cargo")).unwrap();
+        std::fs::create_dir_all(root.join("nested/.cargo")).unwrap();
+        std::fs::write(root.join(".cargo/config.toml"), "[net]\noffline = true\n").unwrap();
+        std::fs::write(root.join("nested/.cargo/config.toml"), "[net]\n").unwrap();
+        let config = load_config(&root.join("nested/Cargo.toml")).unwrap();
+        assert_eq!(config.offline, Some(true));
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use url::Url;
+
+use super::errors::*;
+use super::fetch::{
+    fuzzy_query_registry_index, latest_dependency, latest_matching_version, open_registry_source,
+};
+use super::registry::registry_url;
+use super::source::{CrateVersion, RegistrySource};
+use super::{Dependency, RustVersion};
+
+/// Versions of crates, by registry and name
+type VersionCache = HashMap<(Url, String), Arc<Vec<CrateVersion>>>;
+
+/// Versions an existing requirement on a crate can be upgraded to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpgradeCandidates {
+    /// The latest version the requirement matches, if any
+    pub compatible: Option<semver::Version>,
+    /// The latest version, as [`VersionResolver::latest_dependency`] picks it
+    pub latest: semver::Version,
+}
+
+/// Most lookups running at once
+const MAX_CONCURRENT_LOOKUPS: usize = 16;
+
+/// Looks up the latest versions of crates for a whole run
+///
+/// Each registry is opened once and each crate looked up once per registry, however many
+/// manifests depend on it. [`prefetch`](Self::prefetch) looks crates up concurrently, while
+/// [`latest_dependency`](Self::latest_dependency) answers in the order it is called, so the output
+/// doesn't depend on which lookup finished first.
+pub struct VersionResolver {
+    manifest_path: PathBuf,
+    offline: bool,
+    default_registry: Url,
+    sources: Mutex<HashMap<Url, Arc<dyn RegistrySource>>>,
+    versions: Mutex<VersionCache>,
+}
+
+impl std::fmt::Debug for VersionResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VersionResolver")
+            .field("manifest_path", &self.manifest_path)
+            .field("offline", &self.offline)
+            .field("default_registry", &self.default_registry)
+            .finish_non_exhaustive()
+    }
+}
+
+impl VersionResolver {
+    /// Resolve versions from the registries configured for `manifest_path`, reading Cargo's
+    /// local cache when `offline`
+    pub fn new(manifest_path: &Path, offline: bool) -> CargoResult<Self> {
+        Ok(VersionResolver {
+            manifest_path: manifest_path.to_owned(),
+            offline,
+            default_registry: registry_url(manifest_path, None)?,
+            sources: Default::default(),
+            versions: Default::default(),
+        })
+    }
+
+    /// Look up `crates` concurrently, each with the registry it comes from or the default one
+    ///
+    /// Failed lookups aren't remembered, so [`latest_dependency`](Self::latest_dependency) reports
+    /// their errors when it gets to them.
+    pub fn prefetch<'a>(&self, crates: impl IntoIterator<Item = (&'a str, Option<&'a Url>)>) {
+        let lookups: BTreeSet<_> = crates
+            .into_iter()
+            .map(|(name, registry)| (self.registry(registry).clone(), name.to_owned()))
+            .filter(|key| !self.versions.lock().unwrap().contains_key(key))
+            .collect();
+        let workers = std::thread::available_parallelism()
+            .map_or(1, usize::from)
+            .clamp(1, MAX_CONCURRENT_LOOKUPS)
+            .min(lookups.len());
+        let queue = Mutex::new(lookups.into_iter());
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let next = queue.lock().unwrap().next();
+                    match next {
+                        Some((registry, name)) => {
+                            let _ = self.versions(&name, &registry);
+                        }
+                        None => break,
+                    }
+                });
+            }
+        });
+    }
+
+    /// The latest version of `crate_name` in `registry`, or in the default registry, supporting
+    /// `rust_version` when there is one
+    pub fn latest_dependency(
+        &self,
+        crate_name: &str,
+        flag_allow_prerelease: bool,
+        registry: Option<&Url>,
+        rust_version: Option<RustVersion>,
+    ) -> CargoResult<Dependency> {
+        if crate_name.is_empty() {
+            anyhow::bail!("Found empty crate name");
+        }
+        let versions = self.versions(crate_name, self.registry(registry))?;
+        latest_dependency(&versions, crate_name, flag_allow_prerelease, rust_version)
+    }
+
+    /// The versions `req` on `crate_name` can be upgraded to, from every version in `registry`,
+    /// or in the default registry
+    pub fn upgrade_candidates(
+        &self,
+        crate_name: &str,
+        req: &semver::VersionReq,
+        flag_allow_prerelease: bool,
+        registry: Option<&Url>,
+        rust_version: Option<RustVersion>,
+    ) -> CargoResult<UpgradeCandidates> {
+        let latest =
+            self.latest_dependency(crate_name, flag_allow_prerelease, registry, rust_version)?;
+        let latest = latest.version().expect("latest dependency has a version");
+        let versions = self.versions(crate_name, self.registry(registry))?;
+        Ok(UpgradeCandidates {
+            compatible: latest_matching_version(
+                &versions,
+                req,
+                flag_allow_prerelease,
+                rust_version,
+            )
+            .map(|v| v.version.clone()),
+            latest: latest
+                .parse()
+                .map_err(|_| parse_version_err(latest, crate_name))?,
+        })
+    }
+
+    /// Whether `version` of `crate_name` was yanked from `registry`, or from the default registry
+    pub fn is_yanked(
+        &self,
+        crate_name: &str,
+        version: &semver::Version,
+        registry: Option<&Url>,
+    ) -> CargoResult<bool> {
+        let versions = self.versions(crate_name, self.registry(registry))?;
+        Ok(versions.iter().any(|v| v.yanked && v.version == *version))
+    }
+
+    fn registry<'a>(&'a self, registry: Option<&'a Url>) -> &'a Url {
+        registry.unwrap_or(&self.default_registry)
+    }
+
+    /// The registry at `registry`, opened on first use
+    fn source(&self, registry: &Url) -> CargoResult<Arc<dyn RegistrySource>> {
+        let mut sources = self.sources.lock().unwrap();
+        if let Some(source) = sources.get(registry) {
+            return Ok(source.clone());
+        }
+        let source: Arc<dyn RegistrySource> =
+            open_registry_source(registry, &self.manifest_path, self.offline)?.into();
+        sources.insert(registry.clone(), source.clone());
+        Ok(source)
+    }
+
+    /// Every version of `crate_name`, looked up once per registry
+    fn versions(&self, crate_name: &str, registry: &Url) -> CargoResult<Arc<Vec<CrateVersion>>> {
+        let key = (registry.clone(), crate_name.to_owned());
+        if let Some(versions) = self.versions.lock().unwrap().get(&key) {
+            return Ok(versions.clone());
+        }
+        let source = self.source(registry)?;
+        let versions = Arc::new(fuzzy_query_registry_index(crate_name, source.as_ref())?);
+        self.versions.lock().unwrap().insert(key, versions.clone());
+        Ok(versions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remember_lookups() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = dunce::canonicalize(temp.path()).unwrap();
+        std::fs::create_dir_all(root.join(".cargo")).unwrap();
+        std::fs::write(
+            root.join(".cargo/config.toml"),
+            "[source.crates-io]\nreplace-with = \"local\"\n\n[source.local]\nlocal-registry = \"registry\"\n",
+        )
+        .unwrap();
+        let line = |name: &str, version: &str, yanked: bool| {
+            format!(
+                r#"{{"name":"{}","vers":"{}","deps":[],"features":{{}},"cksum":"{}","yanked":{}}}"#,
+                name,
+                version,
+                "0".repeat(64),
+                yanked
+            )
+        };
+        let foo = [line("foo", "0.0.9", true), line("foo", "0.1.0", false)].join("\n");
+        for (path, contents) in [("3/f/foo", foo), ("3/b/bar", line("bar", "0.2.0", false))] {
+            let path = root.join("registry/index").join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        let resolver = VersionResolver::new(&root.join("Cargo.toml"), false).unwrap();
+        resolver.prefetch([("foo", None), ("bar", None), ("baz", None), ("foo", None)]);
+        // Only the answers remembered by now are left
+        std::fs::remove_dir_all(root.join("registry/index")).unwrap();
+        let version = |name: &str| {
+            resolver
+                .latest_dependency(name, false, None, None)
+                .map(|dep| dep.version().unwrap().to_owned())
+        };
+        assert_eq!(version("foo").unwrap(), "0.1.0");
+        assert_eq!(version("bar").unwrap(), "0.2.0");
+        assert!(version("baz").is_err());
+
+        let candidates = resolver
+            .upgrade_candidates("foo", &"0.0.1".parse().unwrap(), false, None, None)
+            .unwrap();
+        assert_eq!(candidates.compatible, None);
+        assert_eq!(candidates.latest, semver::Version::new(0, 1, 0));
+        let candidates = resolver
+            .upgrade_candidates("foo", &"0.1".parse().unwrap(), false, None, None)
+            .unwrap();
+        assert_eq!(candidates.compatible, Some(semver::Version::new(0, 1, 0)));
+
+        let yanked = |version| resolver.is_yanked("foo", &version, None).unwrap();
+        assert!(yanked(semver::Version::new(0, 0, 9)));
+        assert!(!yanked(semver::Version::new(0, 1, 0)));
+    }
+}
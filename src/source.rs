@@ -0,0 +1,415 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::errors::*;
+use super::fetch::index_path;
+use super::sparse::SparseIndex;
+use super::RustVersion;
+
+/// Environment variable pointing at a [`FixtureRegistry`] to read instead of every remote
+/// registry
+pub const FIXTURE_REGISTRY_ENV: &str = "CARGO_EDIT_FIXTURE_REGISTRY";
+
+/// A version of a crate, as published in a registry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrateVersion {
+    /// Name of the crate, as published
+    pub name: String,
+    /// The version
+    pub version: semver::Version,
+    /// Whether the version was yanked
+    pub yanked: bool,
+    /// Features and the values they activate
+    pub available_features: BTreeMap<String, Vec<String>>,
+    /// Dependencies the features can refer to, and whether they are optional
+    pub available_dependencies: BTreeMap<String, bool>,
+    /// Oldest version of Rust the crate supports, if it declares one
+    pub rust_version: Option<RustVersion>,
+}
+
+/// Where the versions of crates are looked up
+///
+/// Git and sparse indexes, Cargo's local sources and [`FixtureRegistry`] implement it, and
+/// [`open_registry_source`](crate::open_registry_source) picks the one a registry URL refers to.
+/// Sources are shared between threads looking crates up concurrently.
+pub trait RegistrySource: Send + Sync {
+    /// Every version of `crate_name`, if the registry has it
+    fn versions(&self, crate_name: &str) -> CargoResult<Option<Vec<CrateVersion>>>;
+
+    /// Error for a crate the registry doesn't have
+    fn crate_not_found(&self, crate_name: &str) -> Error {
+        no_crate_err(crate_name)
+    }
+}
+
+/// A git index
+///
+/// Lookups are serialized, as the repository can't be read from several threads at once.
+pub struct GitIndex {
+    url: String,
+    repo: Mutex<git2::Repository>,
+    head: git2::Oid,
+}
+
+impl std::fmt::Debug for GitIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("GitIndex").field(&self.url).finish()
+    }
+}
+
+impl GitIndex {
+    /// Read the versions of crates from `index`, as last fetched
+    pub fn new(index: &crates_index::Index) -> CargoResult<Self> {
+        let repo = git2::Repository::open(index.path())
+            .with_context(|| format!("Failed to open registry index `{}`", index.url()))?;
+        let head = repo
+            .refname_to_id("FETCH_HEAD")
+            .or_else(|_| repo.refname_to_id("HEAD"))
+            .with_context(|| format!("Registry index `{}` was never fetched", index.url()))?;
+        Ok(GitIndex {
+            url: index.url().to_owned(),
+            repo: Mutex::new(repo),
+            head,
+        })
+    }
+
+    /// The index file of `crate_name`, if the index has it
+    fn file(&self, crate_name: &str) -> CargoResult<Option<Vec<u8>>> {
+        let path = match index_path(crate_name) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let repo = self.repo.lock().unwrap_or_else(|err| err.into_inner());
+        let tree = repo.find_commit(self.head)?.tree()?;
+        let entry = match tree.get_path(Path::new(&path)) {
+            Ok(entry) => entry,
+            Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let blob = entry.to_object(&repo)?.peel_to_blob()?;
+        Ok(Some(blob.content().to_owned()))
+    }
+}
+
+impl RegistrySource for GitIndex {
+    fn versions(&self, crate_name: &str) -> CargoResult<Option<Vec<CrateVersion>>> {
+        self.file(crate_name)?
+            .map(|file| index_file_versions(crate_name, &file))
+            .transpose()
+    }
+}
+
+impl RegistrySource for SparseIndex {
+    fn versions(&self, crate_name: &str) -> CargoResult<Option<Vec<CrateVersion>>> {
+        self.file(crate_name)?
+            .map(|file| index_file_versions(crate_name, &file))
+            .transpose()
+    }
+}
+
+/// A registry made of a directory with a `<crate>.toml` file per crate, for deterministic tests
+///
+/// Each file lists the versions of the crate:
+///
+/// ```toml
+/// [[versions]]
+/// version = "1.0.0"
+/// yanked = false
+/// features = { std = [], derive = ["dep:serde_derive"] }
+/// dependencies = { serde_derive = { optional = true } }
+/// rust-version = "1.60"
+/// ```
+///
+/// Setting `CARGO_EDIT_FIXTURE_REGISTRY` to such a directory makes cargo-edit read it instead of
+/// any remote registry.
+#[derive(Clone, Debug)]
+pub struct FixtureRegistry {
+    root: PathBuf,
+}
+
+impl FixtureRegistry {
+    /// The fixture registry in `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FixtureRegistry { root: root.into() }
+    }
+
+    /// The fixture registry `CARGO_EDIT_FIXTURE_REGISTRY` points at, if any
+    pub fn from_env() -> Option<Self> {
+        std::env::var_os(FIXTURE_REGISTRY_ENV)
+            .filter(|root| !root.is_empty())
+            .map(Self::new)
+    }
+}
+
+#[derive(Deserialize)]
+struct FixtureCrate {
+    #[serde(default)]
+    versions: Vec<FixtureVersion>,
+}
+
+#[derive(Deserialize)]
+struct FixtureVersion {
+    version: String,
+    #[serde(default)]
+    yanked: bool,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, FixtureDependency>,
+    #[serde(rename = "rust-version")]
+    rust_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FixtureDependency {
+    #[serde(default)]
+    optional: bool,
+}
+
+impl RegistrySource for FixtureRegistry {
+    fn versions(&self, crate_name: &str) -> CargoResult<Option<Vec<CrateVersion>>> {
+        if crate_name.is_empty() || crate_name.contains(['/', '\\', '.']) {
+            return Ok(None);
+        }
+        let path = self.root.join(format!("{}.toml", crate_name));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let crate_: FixtureCrate = toml_edit::easy::from_str(&content)
+            .with_context(|| format!("Invalid fixture crate {}", path.display()))?;
+        crate_
+            .versions
+            .into_iter()
+            .map(|v| {
+                let available_dependencies: BTreeMap<_, _> = v
+                    .dependencies
+                    .into_iter()
+                    .map(|(name, dep)| (name, dep.optional))
+                    .collect();
+                Ok(CrateVersion {
+                    name: crate_name.to_owned(),
+                    version: v.version.parse().with_context(|| {
+                        format!("Invalid version `{}` in {}", v.version, path.display())
+                    })?,
+                    yanked: v.yanked,
+                    available_features: implicit_features(v.features, &available_dependencies),
+                    available_dependencies,
+                    rust_version: v
+                        .rust_version
+                        .map(|rust_version| rust_version.parse())
+                        .transpose()
+                        .with_context(|| format!("Invalid rust-version in {}", path.display()))?,
+                })
+            })
+            .collect::<CargoResult<Vec<_>>>()
+            .map(Some)
+    }
+}
+
+/// Read the versions of `crate_name` from its index file, one JSON line per version
+pub(crate) fn index_file_versions(crate_name: &str, file: &[u8]) -> CargoResult<Vec<CrateVersion>> {
+    let crate_ = crates_index::Crate::from_slice(file)
+        .with_context(|| format!("Invalid index file for `{}`", crate_name))?;
+    // `crates_index` drops the `rust_version` of versions, so it is read from the lines directly
+    let rust_versions: HashMap<_, _> = file
+        .split(|byte| *byte == b'\n')
+        .filter_map(|line| serde_json::from_slice::<IndexLine>(line).ok())
+        .filter_map(|line| Some((line.vers, line.rust_version?.parse().ok()?)))
+        .collect();
+    crate_
+        .versions()
+        .iter()
+        .map(|v| {
+            Ok(CrateVersion {
+                name: v.name().to_owned(),
+                version: v.version().parse()?,
+                yanked: v.is_yanked(),
+                available_features: registry_features(v),
+                available_dependencies: registry_dependencies(v),
+                rust_version: rust_versions.get(v.version()).copied(),
+            })
+        })
+        .collect()
+}
+
+/// The fields of an index line `crates_index` doesn't read
+#[derive(Deserialize)]
+struct IndexLine {
+    vers: String,
+    rust_version: Option<String>,
+}
+
+fn registry_features(v: &crates_index::Version) -> BTreeMap<String, Vec<String>> {
+    let features = v
+        .features()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    implicit_features(features, &registry_dependencies(v))
+}
+
+/// Add the features optional dependencies imply, named after them, unless a feature refers to
+/// them with `dep:`
+fn implicit_features(
+    mut features: BTreeMap<String, Vec<String>>,
+    dependencies: &BTreeMap<String, bool>,
+) -> BTreeMap<String, Vec<String>> {
+    let explicit: Vec<_> = features
+        .values()
+        .flatten()
+        .filter_map(|value| value.strip_prefix("dep:"))
+        .map(|name| name.to_owned())
+        .collect();
+    for (name, optional) in dependencies {
+        if *optional && !explicit.contains(name) {
+            features.entry(name.clone()).or_default();
+        }
+    }
+    features
+}
+
+/// Dependencies the features of a crate can refer to, and whether they are optional
+fn registry_dependencies(v: &crates_index::Version) -> BTreeMap<String, bool> {
+    let mut dependencies = BTreeMap::new();
+    for dep in v.dependencies() {
+        if dep.kind() == crates_index::DependencyKind::Dev {
+            continue;
+        }
+        *dependencies.entry(dep.name().to_owned()).or_insert(false) |= dep.is_optional();
+    }
+    dependencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_features_follow_dep_syntax() {
+        let dep = |name: &str, package: &str, optional: bool, kind: &str| {
+            format!(
+                r#"{{"name":"{}","package":"{}","req":"1","features":[],"optional":{},"default_features":true,"target":null,"kind":"{}"}}"#,
+                name, package, optional, kind
+            )
+        };
+        let deps = [
+            dep("exposed", "exposed", true, "normal"),
+            dep("hidden", "hidden", true, "normal"),
+            dep("renamed", "original", true, "normal"),
+            dep("required", "required", false, "build"),
+            dep("tests", "tests", false, "dev"),
+        ];
+        let line = format!(
+            r#"{{"name":"demo","vers":"1.0.0","deps":[{}],"features":{{"std":["required/std"]}},"features2":{{"extra":["dep:hidden"]}},"cksum":"{}"}}"#,
+            deps.join(","),
+            "0".repeat(64)
+        );
+        let crate_ = crates_index::Crate::from_slice(line.as_bytes()).unwrap();
+        let version = crate_.highest_version();
+        assert_eq!(
+            registry_features(version).keys().collect::<Vec<_>>(),
+            ["exposed", "extra", "renamed", "std"]
+        );
+        assert_eq!(
+            registry_dependencies(version)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [
+                ("exposed".to_owned(), true),
+                ("hidden".to_owned(), true),
+                ("renamed".to_owned(), true),
+                ("required".to_owned(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_rust_version_from_index() {
+        let line = |version: &str, rust_version: &str| {
+            format!(
+                r#"{{"name":"foo","vers":"{}","deps":[],"features":{{}},"cksum":"{}"{}}}"#,
+                version,
+                "0".repeat(64),
+                rust_version
+            )
+        };
+        let file = [
+            line("0.1.0", ""),
+            line("0.2.0", r#","rust_version":"1.60""#),
+            line("0.3.0", r#","rust_version":"invalid""#),
+        ]
+        .join("\n");
+        let versions = index_file_versions("foo", file.as_bytes()).unwrap();
+        assert_eq!(
+            versions
+                .iter()
+                .map(|v| v.rust_version.map(|rust_version| rust_version.to_string()))
+                .collect::<Vec<_>>(),
+            [None, Some("1.60".to_owned()), None]
+        );
+    }
+
+    #[test]
+    fn read_git_index() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let repo = git2::Repository::init(temp.path()).unwrap();
+        let line = format!(
+            r#"{{"name":"foo","vers":"0.1.0","deps":[],"features":{{}},"cksum":"{}","rust_version":"1.60"}}"#,
+            "0".repeat(64)
+        );
+        std::fs::create_dir_all(temp.path().join("3/f")).unwrap();
+        std::fs::write(temp.path().join("3/f/foo"), line).unwrap();
+        let mut tree = repo.index().unwrap();
+        tree.add_path(Path::new("3/f/foo")).unwrap();
+        let tree = repo.find_tree(tree.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "index", &tree, &[])
+            .unwrap();
+
+        let index =
+            crates_index::Index::with_path(temp.path(), "https://example.com/index").unwrap();
+        let index = GitIndex::new(&index).unwrap();
+        let versions = index.versions("foo").unwrap().unwrap();
+        assert_eq!(versions[0].version, semver::Version::new(0, 1, 0));
+        assert_eq!(versions[0].rust_version, Some("1.60".parse().unwrap()));
+        assert!(index.versions("bar").unwrap().is_none());
+    }
+
+    #[test]
+    fn read_fixture_registry() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        std::fs::write(
+            temp.path().join("foo.toml"),
+            r#"
+[[versions]]
+version = "0.1.0"
+
+[[versions]]
+version = "0.2.0"
+yanked = true
+features = { std = [], derive = ["dep:foo_derive"] }
+dependencies = { foo_derive = { optional = true }, log = { optional = true }, libc = {} }
+rust-version = "1.60"
+"#,
+        )
+        .unwrap();
+
+        let registry = FixtureRegistry::new(temp.path());
+        let versions = registry.versions("foo").unwrap().unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[1].version, semver::Version::new(0, 2, 0));
+        assert!(versions[1].yanked);
+        assert_eq!(
+            versions[1].available_features.keys().collect::<Vec<_>>(),
+            ["derive", "log", "std"]
+        );
+        assert_eq!(versions[1].available_dependencies.get("libc"), Some(&false));
+        assert_eq!(versions[0].rust_version, None);
+        assert_eq!(versions[1].rust_version, Some("1.60".parse().unwrap()));
+        assert!(registry.versions("bar").unwrap().is_none());
+        assert!(registry.versions("../foo").unwrap().is_none());
+    }
+}
@@ -0,0 +1,381 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use url::Url;
+
+use super::credentials::{Credentials, Token};
+use super::errors::*;
+use super::fetch::{http_agent, index_path};
+use super::registry::cache_dir;
+
+/// A registry index served over HTTP with the sparse protocol, see
+/// <https://doc.rust-lang.org/cargo/reference/registry-index.html#sparse-protocol>
+///
+/// Only the files of the crates looked up are fetched. Like Cargo, responses are cached along
+/// with their `ETag` or `Last-Modified` header so files that didn't change aren't downloaded
+/// again.
+///
+/// Registries requiring authentication get the token of the registry on every request.
+pub(crate) struct SparseIndex {
+    url: Url,
+    cache: PathBuf,
+    agent: ureq::Agent,
+    token: Option<Token>,
+}
+
+/// Outcome of a request to the index
+enum Response {
+    Found(Vec<u8>),
+    Missing,
+    Unauthorized,
+}
+
+/// The `config.json` at the root of an index
+#[derive(Debug, Deserialize)]
+struct IndexConfig {
+    /// Whether every request needs a token
+    #[serde(rename = "auth-required", default)]
+    auth_required: bool,
+}
+
+impl SparseIndex {
+    /// Open the index at `url`, without its `sparse+` prefix, fetching its `config.json`
+    pub(crate) fn open(url: &Url, credentials: Option<&Credentials>) -> CargoResult<Self> {
+        Self::with_cache(url, cache_dir("sparse", url)?, credentials)
+    }
+
+    fn with_cache(
+        url: &Url,
+        cache: PathBuf,
+        credentials: Option<&Credentials>,
+    ) -> CargoResult<Self> {
+        let mut url = url.clone();
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        let mut index = SparseIndex {
+            agent: http_agent(url.as_str())?,
+            url,
+            cache,
+            token: None,
+        };
+
+        // Like Cargo, only send the token once the registry asked for it
+        let mut config = index.fetch("config.json")?;
+        if let Response::Unauthorized = config {
+            index.token = Some(index.token(credentials)?);
+            config = index.fetch("config.json")?;
+        }
+        let config = match config {
+            Response::Found(config) => config,
+            Response::Missing => {
+                anyhow::bail!("The registry index `{}` has no `config.json`", index.url)
+            }
+            Response::Unauthorized => return Err(index.unauthorized()),
+        };
+        let config: IndexConfig = serde_json::from_slice(&config)
+            .with_context(|| format!("Invalid `config.json` in registry index `{}`", index.url))?;
+        if config.auth_required && index.token.is_none() {
+            index.token = Some(index.token(credentials)?);
+        }
+        Ok(index)
+    }
+
+    /// The token of the registry, which must have one
+    fn token(&self, credentials: Option<&Credentials>) -> CargoResult<Token> {
+        let credentials = credentials.with_context(|| {
+            format!(
+                "The registry index `{}` requires authentication but is not a registry configured in `[registries]`",
+                self.url
+            )
+        })?;
+        credentials.token()?.with_context(|| {
+            format!(
+                "The registry index `{}` requires authentication but no token is configured for registry `{}`",
+                self.url,
+                credentials.name()
+            )
+        })
+    }
+
+    fn unauthorized(&self) -> Error {
+        anyhow::format_err!(
+            "The registry index `{}` rejected the token of the registry",
+            self.url
+        )
+    }
+
+    /// The index file of `crate_name`, if the registry has it
+    pub(crate) fn file(&self, crate_name: &str) -> CargoResult<Option<Vec<u8>>> {
+        let path = match index_path(crate_name) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        match self.fetch(&path)? {
+            Response::Found(file) => Ok(Some(file)),
+            Response::Missing => Ok(None),
+            Response::Unauthorized => Err(self.unauthorized()),
+        }
+    }
+
+    /// Fetch the file at `path` in the index, revalidating the cached response if there is one
+    fn fetch(&self, path: &str) -> CargoResult<Response> {
+        let url = self.url.join(path)?;
+        let cache_path = self.cache.join(path);
+        let cached = read_cache(&cache_path);
+
+        let mut request = self.agent.get(url.as_str());
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", token.as_str());
+        }
+        if let Some((validator, _)) = &cached {
+            if let Some(etag) = validator.strip_prefix("etag: ") {
+                request = request.set("If-None-Match", etag);
+            } else if let Some(last_modified) = validator.strip_prefix("last-modified: ") {
+                request = request.set("If-Modified-Since", last_modified);
+            }
+        }
+        match request.call() {
+            Ok(response) if response.status() == 304 => cached
+                .map(|(_, body)| Response::Found(body))
+                .with_context(|| format!("Unexpected `304 Not Modified` for `{}`", url)),
+            Ok(response) => {
+                let validator = match (response.header("etag"), response.header("last-modified")) {
+                    (Some(etag), _) => format!("etag: {}", etag),
+                    (None, Some(last_modified)) => format!("last-modified: {}", last_modified),
+                    (None, None) => String::new(),
+                };
+                let mut body = Vec::new();
+                response
+                    .into_reader()
+                    .read_to_end(&mut body)
+                    .with_context(|| format!("Failed to read `{}`", url))?;
+                // The cache only saves downloads, failing to write it is fine
+                let _ = write_cache(&cache_path, &validator, &body);
+                Ok(Response::Found(body))
+            }
+            // Like Cargo, consider these as the file not existing
+            Err(ureq::Error::Status(404 | 410 | 451, _)) => {
+                let _ = std::fs::remove_file(&cache_path);
+                Ok(Response::Missing)
+            }
+            Err(ureq::Error::Status(401, _)) => Ok(Response::Unauthorized),
+            Err(err) => Err(anyhow::format_err!(
+                "HTTP request `{}` failed: {}",
+                url,
+                err
+            )),
+        }
+    }
+}
+
+/// Read a cached response, stored as the header validating it on the first line followed by the
+/// body
+fn read_cache(path: &Path) -> Option<(String, Vec<u8>)> {
+    let content = std::fs::read(path).ok()?;
+    let newline = content.iter().position(|byte| *byte == b'\n')?;
+    let validator = String::from_utf8(content[..newline].to_vec()).ok()?;
+    Some((validator, content[newline + 1..].to_vec()))
+}
+
+fn write_cache(path: &Path, validator: &str, body: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut content = format!("{}\n", validator).into_bytes();
+    content.extend_from_slice(body);
+    std::fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RegistrySource;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    type Files = Arc<Mutex<HashMap<String, String>>>;
+
+    /// Serve `files` below `/index/`, answering `304 Not Modified` when the `ETag` matches and
+    /// `401 Unauthorized` without `token`, and log the responses
+    fn serve(files: Files, token: Option<&'static str>) -> (Url, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/index", listener.local_addr().unwrap());
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let responses = log.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let path = path.strip_prefix("/index/").unwrap_or(path).to_owned();
+                let mut if_none_match = None;
+                let mut authorization = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(": ") {
+                        if name.eq_ignore_ascii_case("if-none-match") {
+                            if_none_match = Some(value.to_owned());
+                        } else if name.eq_ignore_ascii_case("authorization") {
+                            authorization = Some(value.to_owned());
+                        }
+                    }
+                }
+
+                let body = files.lock().unwrap().get(&path).cloned();
+                let (status, etag, body) = match body {
+                    _ if token.is_some() && authorization.as_deref() != token => {
+                        ("401 Unauthorized", String::new(), String::new())
+                    }
+                    Some(body) => {
+                        let mut hasher = DefaultHasher::new();
+                        body.hash(&mut hasher);
+                        let etag = format!("\"{:x}\"", hasher.finish());
+                        if if_none_match.as_ref() == Some(&etag) {
+                            ("304 Not Modified", etag, String::new())
+                        } else {
+                            ("200 OK", etag, body)
+                        }
+                    }
+                    None => ("404 Not Found", String::new(), String::new()),
+                };
+                responses
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", &status[..3], path));
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    etag,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        (Url::parse(&url).unwrap(), log)
+    }
+
+    fn version_line(version: &str) -> String {
+        format!(
+            r#"{{"name":"foo","vers":"{}","deps":[],"features":{{}},"cksum":"{}"}}"#,
+            version,
+            "0".repeat(64)
+        )
+    }
+
+    #[test]
+    fn fetch_and_revalidate() {
+        let files: Files = Default::default();
+        files.lock().unwrap().insert(
+            "config.json".to_owned(),
+            r#"{"dl":"https://example.com/dl","api":null}"#.to_owned(),
+        );
+        files
+            .lock()
+            .unwrap()
+            .insert("3/f/foo".to_owned(), version_line("0.1.0"));
+        let (url, log) = serve(files.clone(), None);
+        let cache = assert_fs::TempDir::new().unwrap();
+
+        let index = SparseIndex::with_cache(&url, cache.path().to_owned(), None).unwrap();
+        let versions = |index: &SparseIndex| {
+            index
+                .versions("foo")
+                .unwrap()
+                .unwrap()
+                .iter()
+                .map(|version| version.version.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(versions(&index), ["0.1.0"]);
+        assert!(index.file("bar").unwrap().is_none());
+        assert_eq!(versions(&index), ["0.1.0"]);
+        files.lock().unwrap().insert(
+            "3/f/foo".to_owned(),
+            format!("{}\n{}", version_line("0.1.0"), version_line("0.2.0")),
+        );
+        assert_eq!(versions(&index), ["0.1.0", "0.2.0"]);
+        // A new instance picks up the cache
+        SparseIndex::with_cache(&url, cache.path().to_owned(), None).unwrap();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "200 config.json",
+                "200 3/f/foo",
+                "404 3/b/bar",
+                "304 3/f/foo",
+                "200 3/f/foo",
+                "304 config.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn require_config() {
+        let files: Files = Default::default();
+        let (url, _) = serve(files.clone(), None);
+        let cache = assert_fs::TempDir::new().unwrap();
+        assert!(SparseIndex::with_cache(&url, cache.path().to_owned(), None).is_err());
+
+        files.lock().unwrap().insert(
+            "config.json".to_owned(),
+            r#"{"dl":"https://example.com/dl","auth-required":true}"#.to_owned(),
+        );
+        assert!(SparseIndex::with_cache(&url, cache.path().to_owned(), None).is_err());
+    }
+
+    #[test]
+    fn authenticate() {
+        let files: Files = Default::default();
+        files.lock().unwrap().insert(
+            "config.json".to_owned(),
+            r#"{"dl":"https://example.com/dl","auth-required":true}"#.to_owned(),
+        );
+        files
+            .lock()
+            .unwrap()
+            .insert("3/f/foo".to_owned(), version_line("0.1.0"));
+        let (url, log) = serve(files, Some("secret-token"));
+        let cache = assert_fs::TempDir::new().unwrap();
+
+        let err = SparseIndex::with_cache(&url, cache.path().to_owned(), None)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("requires authentication"));
+        let credentials = Credentials::with_token("internal", "wrong-token");
+        let err = SparseIndex::with_cache(&url, cache.path().to_owned(), Some(&credentials))
+            .err()
+            .unwrap();
+        assert!(!format!("{:?}", err).contains("wrong-token"));
+
+        let credentials = Credentials::with_token("internal", "secret-token");
+        let index =
+            SparseIndex::with_cache(&url, cache.path().to_owned(), Some(&credentials)).unwrap();
+        assert!(index.file("foo").unwrap().is_some());
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "401 config.json",
+                "401 config.json",
+                "401 config.json",
+                "401 config.json",
+                "200 config.json",
+                "200 3/f/foo",
+            ]
+        );
+    }
+}
@@ -0,0 +1,417 @@
+//! All-or-nothing writes of several manifests.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::errors::*;
+use super::manifest::LocalManifest;
+
+/// A set of manifest edits that are written all-or-nothing.
+///
+/// Manifests are staged as they are edited and only touch the disk on
+/// [`ManifestTransaction::commit`], which writes every manifest to a temporary file next to it
+/// before renaming them into place. If anything fails, the original manifests are restored.
+///
+/// Writes keep the byte order mark, line endings, trailing newline and permissions of the
+/// original file, and go through symlinks rather than replacing them.
+///
+/// # Examples
+///
+/// ```no_run
+/// use cargo_edit_9::{LocalManifest, ManifestTransaction};
+/// # fn main() -> cargo_edit_9::CargoResult<()> {
+/// let mut transaction = ManifestTransaction::new();
+/// let mut manifest = transaction.open(std::path::Path::new("Cargo.toml"))?;
+/// manifest.set_package_version(&semver::Version::new(1, 0, 0));
+/// transaction.stage(&manifest)?;
+/// transaction.commit()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct ManifestTransaction {
+    pending: BTreeMap<PathBuf, String>,
+}
+
+impl ManifestTransaction {
+    /// Start an empty transaction
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a manifest, including any edits already staged for it
+    pub fn open(&self, path: &Path) -> CargoResult<LocalManifest> {
+        match self.pending.get(&normalize(path)) {
+            Some(contents) => Ok(LocalManifest {
+                path: path.to_owned(),
+                manifest: contents
+                    .parse()
+                    .with_context(|| "Unable to parse Cargo.toml")?,
+            }),
+            None => LocalManifest::try_new(path),
+        }
+    }
+
+    /// Stage edits to package-level tables, replacing earlier edits to the same manifest
+    pub fn stage(&mut self, manifest: &LocalManifest) -> CargoResult<()> {
+        manifest.check_package()?;
+        self.insert(manifest);
+        Ok(())
+    }
+
+    /// Stage edits to workspace-level tables, replacing earlier edits to the same manifest
+    ///
+    /// Unlike [`ManifestTransaction::stage`], this accepts virtual manifests.
+    pub fn stage_workspace(&mut self, manifest: &LocalManifest) -> CargoResult<()> {
+        manifest.check_workspace()?;
+        self.insert(manifest);
+        Ok(())
+    }
+
+    /// Whether nothing was staged
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Write all staged manifests, restoring the originals if any write fails
+    pub fn commit(self) -> CargoResult<()> {
+        let mut staged = Vec::with_capacity(self.pending.len());
+        for (path, contents) in self.pending {
+            let temp_path = temp_path(&path);
+            let original = fs::read(&path).ok();
+            let format = original
+                .as_deref()
+                .map(FileFormat::detect)
+                .unwrap_or_default();
+            if let Err(err) = write_temp(&path, &temp_path, &format.apply(&contents)) {
+                let _ = fs::remove_file(&temp_path);
+                remove_temp_files(&staged);
+                return Err(err).with_context(|| "Failed to write updated Cargo.toml");
+            }
+            staged.push(Staged {
+                path,
+                temp_path,
+                original,
+            });
+        }
+
+        for (i, file) in staged.iter().enumerate() {
+            if let Err(err) = fs::rename(&file.temp_path, &file.path) {
+                rollback(&staged[..i]);
+                remove_temp_files(&staged[i..]);
+                return Err(err).with_context(|| {
+                    format!(
+                        "Failed to write updated {}, no manifest was modified",
+                        file.path.display()
+                    )
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, manifest: &LocalManifest) {
+        self.pending.insert(
+            normalize(&manifest.path),
+            manifest.manifest.data.to_string(),
+        );
+    }
+}
+
+#[derive(Debug)]
+struct Staged {
+    path: PathBuf,
+    temp_path: PathBuf,
+    original: Option<Vec<u8>>,
+}
+
+/// How the original file was laid out, beyond what the TOML document preserves
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct FileFormat {
+    bom: bool,
+    crlf: bool,
+    trailing_newline: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self {
+            bom: false,
+            crlf: false,
+            trailing_newline: true,
+        }
+    }
+}
+
+impl FileFormat {
+    fn detect(original: &[u8]) -> Self {
+        let (bom, contents) = match original.strip_prefix(BOM) {
+            Some(contents) => (true, contents),
+            None => (false, original),
+        };
+        // Like git, go by the first line ending
+        let crlf = contents
+            .iter()
+            .position(|b| *b == b'\n')
+            .is_some_and(|i| i > 0 && contents[i - 1] == b'\r');
+        let trailing_newline = contents.is_empty() || contents.ends_with(b"\n");
+        Self {
+            bom,
+            crlf,
+            trailing_newline,
+        }
+    }
+
+    fn apply(&self, contents: &str) -> Vec<u8> {
+        let mut contents = contents.to_owned();
+        if self.trailing_newline {
+            if !contents.is_empty() && !contents.ends_with('\n') {
+                contents.push('\n');
+            }
+        } else {
+            while contents.ends_with('\n') {
+                contents.pop();
+                if contents.ends_with('\r') {
+                    contents.pop();
+                }
+            }
+        }
+        if self.crlf {
+            // Lines added by edits only end with `\n`
+            contents = contents.replace("\r\n", "\n").replace('\n', "\r\n");
+        }
+
+        let mut bytes = Vec::with_capacity(contents.len() + BOM.len());
+        if self.bom {
+            bytes.extend_from_slice(BOM);
+        }
+        bytes.extend_from_slice(contents.as_bytes());
+        bytes
+    }
+}
+
+const BOM: &[u8] = "\u{feff}".as_bytes();
+
+fn write_temp(path: &Path, temp_path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    fs::write(temp_path, contents)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(temp_path, metadata.permissions())?;
+    }
+    Ok(())
+}
+
+/// Key manifests by their canonical path so edits through different paths end up together.
+///
+/// This also resolves symlinks, so the rename in [`ManifestTransaction::commit`] replaces the
+/// file they point to rather than the link itself.
+fn normalize(path: &Path) -> PathBuf {
+    dunce::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()))
+}
+
+fn remove_temp_files(staged: &[Staged]) {
+    for file in staged {
+        let _ = fs::remove_file(&file.temp_path);
+    }
+}
+
+/// Best-effort restore of manifests that were already replaced
+fn rollback(replaced: &[Staged]) {
+    for file in replaced {
+        let _ = match &file.original {
+            Some(original) => fs::write(&file.path, original),
+            None => fs::remove_file(&file.path),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(dir: &Path, name: &str, version: &str) -> PathBuf {
+        let crate_dir = dir.join(name);
+        fs::create_dir_all(&crate_dir).unwrap();
+        let path = crate_dir.join("Cargo.toml");
+        fs::write(
+            &path,
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"{}\"\n",
+                name, version
+            ),
+        )
+        .unwrap();
+        dunce::canonicalize(path).unwrap()
+    }
+
+    fn bump(transaction: &mut ManifestTransaction, path: &Path) {
+        let mut manifest = transaction.open(path).unwrap();
+        manifest.set_package_version(&semver::Version::new(2, 0, 0));
+        transaction.stage(&manifest).unwrap();
+    }
+
+    #[test]
+    fn commit_writes_all() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let one = manifest(temp.path(), "one", "1.0.0");
+        let two = manifest(temp.path(), "two", "1.0.0");
+
+        let mut transaction = ManifestTransaction::new();
+        bump(&mut transaction, &one);
+        bump(&mut transaction, &two);
+        assert!(fs::read_to_string(&one).unwrap().contains("1.0.0"));
+        transaction.commit().unwrap();
+
+        assert!(fs::read_to_string(&one).unwrap().contains("2.0.0"));
+        assert!(fs::read_to_string(&two).unwrap().contains("2.0.0"));
+        assert_eq!(fs::read_dir(temp.path().join("one")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn open_sees_staged_edits() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let one = manifest(temp.path(), "one", "1.0.0");
+
+        let mut transaction = ManifestTransaction::new();
+        bump(&mut transaction, &one);
+        let staged = transaction.open(&one).unwrap();
+
+        assert!(staged.to_string().contains("2.0.0"));
+    }
+
+    #[test]
+    fn failed_commit_restores_originals() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let one = manifest(temp.path(), "one", "1.0.0");
+        let two = manifest(temp.path(), "two", "1.0.0");
+
+        let mut transaction = ManifestTransaction::new();
+        bump(&mut transaction, &one);
+        bump(&mut transaction, &two);
+        // Renaming over a non-empty directory fails after `one` was replaced
+        fs::remove_file(&two).unwrap();
+        fs::create_dir_all(two.join("blocker")).unwrap();
+        assert!(transaction.commit().is_err());
+
+        assert!(fs::read_to_string(&one).unwrap().contains("1.0.0"));
+        assert_eq!(fs::read_dir(temp.path().join("one")).unwrap().count(), 1);
+        assert_eq!(fs::read_dir(temp.path().join("two")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn detect_format() {
+        let format = FileFormat::detect(b"\xef\xbb\xbf[package]\r\nname = \"one\"");
+        assert_eq!(
+            format,
+            FileFormat {
+                bom: true,
+                crlf: true,
+                trailing_newline: false
+            }
+        );
+        assert_eq!(
+            FileFormat::detect(b"[package]\nname = \"one\"\n"),
+            FileFormat::default()
+        );
+    }
+
+    #[test]
+    fn apply_format() {
+        let format = FileFormat {
+            bom: true,
+            crlf: true,
+            trailing_newline: false,
+        };
+        assert_eq!(
+            format.apply("[package]\r\nname = \"one\"\nversion = \"1.0.0\"\n"),
+            b"\xef\xbb\xbf[package]\r\nname = \"one\"\r\nversion = \"1.0.0\"".to_vec()
+        );
+        assert_eq!(
+            FileFormat::default().apply("[package]\nname = \"one\""),
+            b"[package]\nname = \"one\"\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn commit_keeps_format() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let path = temp.path().join("Cargo.toml");
+        fs::write(
+            &path,
+            b"\xef\xbb\xbf[package]\r\nname = \"one\"\r\nversion = \"1.0.0\"\r\n\r\n[dependencies]\r\nlibc = \"0.2\"",
+        )
+        .unwrap();
+
+        let mut transaction = ManifestTransaction::new();
+        let mut manifest = transaction.open(&path).unwrap();
+        manifest
+            .insert_into_table(
+                &["dependencies".to_owned()],
+                &crate::Dependency::new("docopt").set_version("0.8"),
+            )
+            .unwrap();
+        transaction.stage(&manifest).unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            b"\xef\xbb\xbf[package]\r\nname = \"one\"\r\nversion = \"1.0.0\"\r\n\r\n[dependencies]\r\nlibc = \"0.2\"\r\ndocopt = \"0.8\"".to_vec()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commit_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let one = manifest(temp.path(), "one", "1.0.0");
+        fs::set_permissions(&one, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let mut transaction = ManifestTransaction::new();
+        bump(&mut transaction, &one);
+        transaction.commit().unwrap();
+
+        let mode = fs::metadata(&one).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commit_writes_through_symlinks() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let one = manifest(temp.path(), "one", "1.0.0");
+        let link = temp.path().join("link.toml");
+        std::os::unix::fs::symlink(&one, &link).unwrap();
+
+        let mut transaction = ManifestTransaction::new();
+        bump(&mut transaction, &link);
+        transaction.commit().unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(fs::read_to_string(&one).unwrap().contains("2.0.0"));
+    }
+
+    #[test]
+    fn virtual_manifest_needs_workspace_staging() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let path = temp.path().join("Cargo.toml");
+        fs::write(&path, "[workspace]\nmembers = []\n").unwrap();
+
+        let mut transaction = ManifestTransaction::new();
+        let manifest = transaction.open(&path).unwrap();
+        assert!(transaction.stage(&manifest).is_err());
+        assert!(transaction.stage_workspace(&manifest).is_ok());
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use super::errors::*;
+use super::manifest::LocalManifest;
+
+/// A dependency never referenced from the sources that can use it
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnusedDependency {
+    /// Table the dependency is declared in, like `dependencies` or
+    /// `target.'cfg(unix)'.dev-dependencies`
+    pub section: Vec<String>,
+    /// Name of the dependency as written in the manifest
+    pub key: String,
+    /// Table the dependency belongs in instead, when only other kinds of targets use it
+    pub move_to: Option<Vec<String>>,
+}
+
+/// Kinds of targets and the dependency tables available to them
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Usage {
+    /// Libraries and binaries, `dependencies`
+    Normal,
+    /// Tests, examples and benchmarks, `dev-dependencies`
+    Dev,
+    /// Build scripts, `build-dependencies`
+    Build,
+}
+
+impl Usage {
+    fn from_kind(kind: &str) -> Self {
+        match kind {
+            "custom-build" => Usage::Build,
+            "test" | "bench" | "example" => Usage::Dev,
+            _ => Usage::Normal,
+        }
+    }
+
+    fn from_table(table: &str) -> Option<Self> {
+        match table {
+            "dependencies" => Some(Usage::Normal),
+            "dev-dependencies" => Some(Usage::Dev),
+            "build-dependencies" => Some(Usage::Build),
+            _ => None,
+        }
+    }
+
+    fn table(self) -> &'static str {
+        match self {
+            Usage::Normal => "dependencies",
+            Usage::Dev => "dev-dependencies",
+            Usage::Build => "build-dependencies",
+        }
+    }
+}
+
+/// Find the dependencies of `package` that none of its targets refer to
+///
+/// Sources are searched for `use`, `extern crate` and paths starting with the dependency's crate
+/// name, so the result errs on the side of considering a dependency used. Dependencies only used
+/// by other kinds of targets, like a `[dependencies]` entry only used by tests, are reported with
+/// the table to move them to. Dependencies missing from the resolved `metadata` are never
+/// reported, as the name of their library isn't known.
+pub fn find_unused_dependencies(
+    manifest: &LocalManifest,
+    package: &cargo_metadata::Package,
+    metadata: &cargo_metadata::Metadata,
+) -> CargoResult<Vec<UnusedDependency>> {
+    let package_root = package
+        .manifest_path
+        .parent()
+        .expect("manifest path is absolute")
+        .as_std_path();
+
+    let mut sources: [Vec<String>; 3] = Default::default();
+    for (usage, files) in target_sources(package, package_root) {
+        for file in files {
+            let contents = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            sources[usage as usize].push(contents);
+        }
+    }
+    let is_used_by = |usage: Usage, crate_name: &str| {
+        let pattern = regex::Regex::new(&format!(
+            r"\b{name}\s*::|\bextern\s+crate\s+{name}\b|\buse\s+(?:::\s*)?{name}\b",
+            name = regex::escape(crate_name)
+        ))
+        .expect("crate names are escaped");
+        sources[usage as usize]
+            .iter()
+            .any(|source| pattern.is_match(source))
+    };
+
+    let crate_names = crate_names(package, metadata);
+    let mut unused = Vec::new();
+    for (section, table) in manifest.get_sections() {
+        let usage = match section.last().and_then(|table| Usage::from_table(table)) {
+            Some(usage) => usage,
+            None => continue,
+        };
+        let table = match table.as_table_like() {
+            Some(table) => table,
+            None => continue,
+        };
+        for (key, _) in table.iter() {
+            let crate_name = match crate_names.get(key) {
+                Some(crate_name) => crate_name.as_str(),
+                None => continue,
+            };
+            let move_to = match usage {
+                Usage::Normal if is_used_by(Usage::Normal, crate_name) => continue,
+                // Unit tests in the library and binaries can use dev-dependencies as well
+                Usage::Dev
+                    if is_used_by(Usage::Dev, crate_name)
+                        || is_used_by(Usage::Normal, crate_name) =>
+                {
+                    continue
+                }
+                Usage::Build if is_used_by(Usage::Build, crate_name) => continue,
+                Usage::Normal => [Usage::Dev, Usage::Build]
+                    .into_iter()
+                    .find(|other| is_used_by(*other, crate_name))
+                    .map(|other| {
+                        let mut move_to = section.clone();
+                        *move_to.last_mut().expect("section is not empty") =
+                            other.table().to_owned();
+                        move_to
+                    }),
+                Usage::Dev | Usage::Build => None,
+            };
+            unused.push(UnusedDependency {
+                section: section.clone(),
+                key: key.to_owned(),
+                move_to,
+            });
+        }
+    }
+
+    Ok(unused)
+}
+
+/// Names the sources of `package` refer to its resolved dependencies by, keyed by their name in
+/// the manifest
+///
+/// That is the name of the dependency's library, unless it is renamed, with `-` not being allowed
+/// in paths.
+fn crate_names(
+    package: &cargo_metadata::Package,
+    metadata: &cargo_metadata::Metadata,
+) -> BTreeMap<String, String> {
+    let node = match metadata
+        .resolve
+        .as_ref()
+        .and_then(|resolve| resolve.nodes.iter().find(|node| node.id == package.id))
+    {
+        Some(node) => node,
+        None => return BTreeMap::new(),
+    };
+    let lib_name = |id: &cargo_metadata::PackageId| {
+        metadata
+            .packages
+            .iter()
+            .find(|p| p.id == *id)
+            .and_then(|p| {
+                p.targets
+                    .iter()
+                    .find(|target| target.kind.iter().any(|kind| is_lib(kind)))
+                    .map(|target| target.name.replace('-', "_"))
+            })
+    };
+
+    let mut names = BTreeMap::new();
+    for dep in &package.dependencies {
+        let resolved = node
+            .deps
+            .iter()
+            .filter(|resolved| metadata[&resolved.pkg].name == dep.name)
+            .find(|resolved| match &dep.rename {
+                Some(rename) => resolved.name == rename.replace('-', "_"),
+                None => lib_name(&resolved.pkg).as_ref() == Some(&resolved.name),
+            });
+        if let Some(resolved) = resolved {
+            let key = dep.rename.as_ref().unwrap_or(&dep.name);
+            names.insert(key.clone(), resolved.name.clone());
+        }
+    }
+    names
+}
+
+/// Whether a target of `kind` can be referred to from other crates
+fn is_lib(kind: &str) -> bool {
+    matches!(kind, "lib" | "rlib" | "dylib" | "proc-macro")
+}
+
+/// Collect the source files of each kind of target
+fn target_sources(
+    package: &cargo_metadata::Package,
+    package_root: &Path,
+) -> Vec<(Usage, BTreeSet<PathBuf>)> {
+    let mut sources: Vec<(Usage, BTreeSet<PathBuf>)> = Vec::new();
+    for target in &package.targets {
+        let usage = target
+            .kind
+            .iter()
+            .map(|kind| Usage::from_kind(kind))
+            .min()
+            .unwrap_or(Usage::Normal);
+        let entry = target.src_path.as_std_path();
+        let mut files = BTreeSet::new();
+        match entry.parent() {
+            // Scanning the package root would pick up every other target, so only follow the
+            // modules of the entry point
+            Some(dir) if dir != package_root => rust_files(dir, &mut files),
+            _ => {}
+        }
+        module_files(entry, true, &mut files);
+
+        match sources.iter_mut().find(|(u, _)| *u == usage) {
+            Some((_, existing)) => existing.extend(files),
+            None => sources.push((usage, files)),
+        }
+    }
+    sources
+}
+
+/// Recursively collect the `.rs` files in `dir`
+fn rust_files(dir: &Path, files: &mut BTreeSet<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            rust_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.insert(path);
+        }
+    }
+}
+
+/// Collect `path` and the files of the modules it declares with `mod name;`
+fn module_files(path: &Path, is_root: bool, files: &mut BTreeSet<PathBuf>) {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return,
+    };
+    files.insert(path.to_owned());
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    // Modules of `foo.rs` live in `foo/`, those of crate roots and `mod.rs` next to them
+    let module_dir = if is_root || path.file_name().is_some_and(|name| name == "mod.rs") {
+        dir.to_owned()
+    } else {
+        dir.join(path.file_stem().unwrap_or_default())
+    };
+    let pattern = regex::Regex::new(
+        r#"(?m)(?:#\[path\s*=\s*"([^"]+)"\]\s*)?^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+(\w+)\s*;"#,
+    )
+    .expect("valid pattern");
+    for captures in pattern.captures_iter(&contents) {
+        let candidates = if let Some(custom) = captures.get(1) {
+            vec![dir.join(custom.as_str())]
+        } else {
+            let name = &captures[2];
+            vec![
+                module_dir.join(format!("{}.rs", name)),
+                module_dir.join(name).join("mod.rs"),
+            ]
+        };
+        if let Some(module) = candidates
+            .into_iter()
+            .find(|candidate| candidate.is_file() && !files.contains(candidate))
+        {
+            module_files(&module, false, files);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follow_modules() {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let root = temp.path();
+        std::fs::write(
+            root.join("build.rs"),
+            "mod helpers;\n#[path = \"gen/codegen.rs\"]\nmod codegen;\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(root.join("helpers")).unwrap();
+        std::fs::write(root.join("helpers.rs"), "pub mod nested;\n").unwrap();
+        std::fs::write(root.join("helpers/nested.rs"), "").unwrap();
+        std::fs::create_dir_all(root.join("gen")).unwrap();
+        std::fs::write(root.join("gen/codegen.rs"), "").unwrap();
+        std::fs::write(root.join("unrelated.rs"), "").unwrap();
+
+        let mut files = BTreeSet::new();
+        module_files(&root.join("build.rs"), true, &mut files);
+        let files: Vec<_> = files
+            .iter()
+            .map(|file| file.strip_prefix(root).unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "build.rs",
+                "gen/codegen.rs",
+                "helpers/nested.rs",
+                "helpers.rs"
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+    }
+}
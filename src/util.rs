@@ -8,3 +8,56 @@ pub fn colorize_stderr() -> ColorChoice {
         ColorChoice::Never
     }
 }
+
+/// Find the candidate closest to `name`, if any is close enough to be a likely typo
+pub fn closest_match<'c>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'c str>,
+) -> Option<&'c str> {
+    let max_distance = std::cmp::max(name.chars().count() / 3, 1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("nose", "nose"), 0);
+        assert_eq!(edit_distance("noze", "nose"), 1);
+        assert_eq!(edit_distance("serde", "sered"), 2);
+        assert_eq!(edit_distance("std", ""), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn closest() {
+        let features = ["nose", "mouth", "eyes", "ears"];
+        assert_eq!(closest_match("noze", features), Some("nose"));
+        assert_eq!(closest_match("eares", features), Some("ears"));
+        assert_eq!(closest_match("tail", features), None);
+        assert_eq!(closest_match("", features), None);
+    }
+}
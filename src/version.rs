@@ -141,6 +141,80 @@ fn prerelease_id_version(version: &semver::Version) -> CargoResult<Option<(Strin
     }
 }
 
+/// A version of Rust, as declared by `rust-version`: `1.70` or `1.70.1`
+///
+/// A missing patch counts as `0`, as it does for Cargo.
+#[derive(Clone, Copy, Debug)]
+pub struct RustVersion {
+    major: u64,
+    minor: Option<u64>,
+    patch: Option<u64>,
+}
+
+impl RustVersion {
+    fn key(&self) -> (u64, u64, u64) {
+        (self.major, self.minor.unwrap_or(0), self.patch.unwrap_or(0))
+    }
+}
+
+impl FromStr for RustVersion {
+    type Err = Error;
+
+    fn from_str(value: &str) -> CargoResult<Self> {
+        let invalid = || anyhow::format_err!("Invalid Rust version `{}`", value);
+        let mut parts = value.trim().split('.').map(|part| {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            u64::from_str(part).map_err(|_| invalid())
+        });
+        let major = parts.next().ok_or_else(invalid)??;
+        let minor = parts.next().transpose()?;
+        let patch = parts.next().transpose()?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(RustVersion {
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+impl std::fmt::Display for RustVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.major)?;
+        if let Some(minor) = self.minor {
+            write!(f, ".{}", minor)?;
+        }
+        if let Some(patch) = self.patch {
+            write!(f, ".{}", patch)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for RustVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for RustVersion {}
+
+impl PartialOrd for RustVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RustVersion {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
 /// Upgrade an existing requirement to a new version
 pub fn upgrade_requirement(req: &str, version: &semver::Version) -> CargoResult<Option<String>> {
     let req_text = req.to_string();
@@ -162,7 +236,7 @@ pub fn upgrade_requirement(req: &str, version: &semver::Version) -> CargoResult<
             new_req_text.remove(0);
         }
         // Validate contract
-        #[cfg(debug_assert)]
+        #[cfg(debug_assertions)]
         {
             assert!(
                 new_req.matches(version),
@@ -226,6 +300,19 @@ fn assign_partial_req(
 mod test {
     use super::*;
 
+    #[test]
+    fn rust_version() {
+        let parse = |v: &str| RustVersion::from_str(v).unwrap();
+        assert_eq!(parse("1.70").to_string(), "1.70");
+        assert_eq!(parse("1.70.1").to_string(), "1.70.1");
+        assert_eq!(parse("1.70"), parse("1.70.0"));
+        assert!(parse("1.70") < parse("1.70.1"));
+        assert!(parse("1.9") < parse("1.10"));
+        assert!(RustVersion::from_str("1.70.0-nightly").is_err());
+        assert!(RustVersion::from_str("1..0").is_err());
+        assert!(RustVersion::from_str("").is_err());
+    }
+
     mod increment {
         use super::*;
 
@@ -1,4 +1,9 @@
 #[test]
 fn cli_tests() {
-    trycmd::TestCases::new().case("tests/cmd/upgrade/*.toml");
+    trycmd::TestCases::new()
+        .env(
+            cargo_edit_9::FIXTURE_REGISTRY_ENV,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/registry"),
+        )
+        .case("tests/cmd/upgrade/*.toml");
 }
@@ -0,0 +1,3 @@
+fn main() {
+    cc::Build::new().file("foo.c").compile("foo");
+}
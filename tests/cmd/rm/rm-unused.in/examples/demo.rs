@@ -0,0 +1,3 @@
+use assert_fs::TempDir;
+
+fn main() {}
@@ -0,0 +1,16 @@
+extern crate semver;
+
+mod util;
+
+pub fn parse(args: &[&str]) -> docopt::ArgvMap {
+    util::parse(args)
+}
+
+pub fn checksum(data: &[u8]) -> md5::Digest {
+    md5::compute(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+}
@@ -0,0 +1,5 @@
+use my_parser::IResult;
+
+pub fn parse(args: &[&str]) -> docopt::ArgvMap {
+    todo!()
+}
@@ -0,0 +1,4 @@
+#[test]
+fn padding() {
+    assert_eq!(pad::PadStr::pad_to_width("a", 2), "a ");
+}
@@ -2,7 +2,7 @@
 fn invalid_manifest() {
     assert_cmd::Command::cargo_bin("cargo-add")
         .expect("can find bin")
-        .args(&[
+        .args([
             "add",
             "foo",
             "--manifest-path=tests/fixtures/manifest-invalid/Cargo.toml.sample",